@@ -0,0 +1,670 @@
+//!
+//! Derives for the `api` crate's model traits: `MongoCollection`,
+//! `Sortable`, `Filterable` and `Projectable`.
+//!
+//! Fields are listed by their name in documents, after serde's `rename`
+//! and `rename_all`. Generated impls name their traits by the absolute
+//! `::api::...` path,
+//! so they only resolve in crates that depend on `api` under that name.
+//! Within `api` itself this relies on `extern crate self as api;` in
+//! `src/lib.rs`, which must stay for the models to derive.
+//!
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Ident, Lit, LitStr, Meta,
+    NestedMeta, Result,
+};
+
+/// Filter operators accepted by `#[filterable(ops = "...")]`
+const FILTER_OPS: &[(&str, &str)] = &[
+    ("eq", "Eq"),
+    ("ne", "Ne"),
+    ("gt", "Gt"),
+    ("gte", "Gte"),
+    ("lt", "Lt"),
+    ("lte", "Lte"),
+    ("in", "In"),
+    ("nin", "Nin"),
+    ("contains", "Contains"),
+];
+
+///
+/// Implements `MongoCollection` from `#[mongo(collection = "...")]`
+///
+#[proc_macro_derive(MongoCollection, attributes(mongo))]
+pub fn derive_mongo_collection(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_mongo_collection(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+///
+/// Implements `Sortable` from fields marked `#[sortable]` or `#[sortable(alias = "...")]`
+///
+#[proc_macro_derive(Sortable, attributes(sortable))]
+pub fn derive_sortable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_sortable(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+///
+/// Implements `Filterable` from fields marked `#[filterable]` or `#[filterable(ops = "...")]`
+///
+#[proc_macro_derive(Filterable, attributes(filterable))]
+pub fn derive_filterable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_filterable(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+///
+/// Implements `Projectable` from fields marked `#[projectable]`
+///
+#[proc_macro_derive(Projectable, attributes(projectable))]
+pub fn derive_projectable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_projectable(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_mongo_collection(input: &DeriveInput) -> Result<TokenStream2> {
+    let ident = &input.ident;
+    let mut collection = None;
+
+    for attr in input.attrs.iter().filter(|a| a.path.is_ident("mongo")) {
+        for (key, value) in attr_args(attr)? {
+            match key.to_string().as_str() {
+                "collection" => collection = Some(str_value(&key, value)?),
+                _ => return Err(Error::new(key.span(), "unknown mongo attribute")),
+            }
+        }
+    }
+
+    let collection = collection.ok_or_else(|| {
+        Error::new(
+            ident.span(),
+            "missing #[mongo(collection = \"...\")] attribute",
+        )
+    })?;
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::api::MongoCollection for #ident #ty_generics #where_clause {
+            fn collection_name() -> &'static str {
+                #collection
+            }
+        }
+    })
+}
+
+fn expand_sortable(input: &DeriveInput) -> Result<TokenStream2> {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut entries = vec![];
+
+    for field in named_fields(input)? {
+        if let Some(attr) = single_attr(field, "sortable")? {
+            let name = field_name(input, field)?;
+            let mut alias = quote!(::std::option::Option::None);
+
+            for (key, value) in attr_args(attr)? {
+                match key.to_string().as_str() {
+                    "alias" => {
+                        let value = str_value(&key, value)?;
+                        alias = quote!(::std::option::Option::Some(#value));
+                    }
+                    _ => return Err(Error::new(key.span(), "unknown sortable attribute")),
+                }
+            }
+
+            entries.push(quote!((#name, #alias)));
+        }
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::api::Sortable for #ident #ty_generics #where_clause {
+            const SORT_FIELDS: &'static [(&'static str, ::std::option::Option<&'static str>)] = &[
+                #(#entries),*
+            ];
+        }
+    })
+}
+
+fn expand_filterable(input: &DeriveInput) -> Result<TokenStream2> {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut entries = vec![];
+
+    for field in named_fields(input)? {
+        if let Some(attr) = single_attr(field, "filterable")? {
+            let name = field_name(input, field)?;
+            let mut ops = quote!(::api::fields::FilterOp::ALL);
+
+            for (key, value) in attr_args(attr)? {
+                match key.to_string().as_str() {
+                    "ops" => {
+                        let value = str_value(&key, value)?;
+                        let variants = value
+                            .split(',')
+                            .map(|op| filter_op(op.trim(), key.span()))
+                            .collect::<Result<Vec<Ident>>>()?;
+                        ops = quote!(&[#(::api::fields::FilterOp::#variants),*]);
+                    }
+                    _ => return Err(Error::new(key.span(), "unknown filterable attribute")),
+                }
+            }
+
+            entries.push(quote!((#name, #ops)));
+        }
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::api::Filterable for #ident #ty_generics #where_clause {
+            const FILTER_FIELDS: &'static [(&'static str, &'static [::api::fields::FilterOp])] = &[
+                #(#entries),*
+            ];
+        }
+    })
+}
+
+fn expand_projectable(input: &DeriveInput) -> Result<TokenStream2> {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut entries = vec![];
+
+    for field in named_fields(input)? {
+        if let Some(attr) = single_attr(field, "projectable")? {
+            if let Some((key, _)) = attr_args(attr)?.into_iter().next() {
+                return Err(Error::new(key.span(), "unknown projectable attribute"));
+            }
+
+            entries.push(field_name(input, field)?);
+        }
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::api::Projectable for #ident #ty_generics #where_clause {
+            const PROJECTION_FIELDS: &'static [&'static str] = &[
+                #(#entries),*
+            ];
+        }
+    })
+}
+
+fn named_fields(input: &DeriveInput) -> Result<impl Iterator<Item = &syn::Field>> {
+    match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => Ok(fields.named.iter()),
+            _ => Err(Error::new(input.ident.span(), "expected named fields")),
+        },
+        _ => Err(Error::new(input.ident.span(), "expected a struct")),
+    }
+}
+
+///
+/// The field's `#[name]` attribute, an error if it is repeated
+///
+fn single_attr<'a>(field: &'a syn::Field, name: &str) -> Result<Option<&'a Attribute>> {
+    let mut attrs = field.attrs.iter().filter(|a| a.path.is_ident(name));
+    let attr = attrs.next();
+
+    match attrs.next() {
+        Some(duplicate) => Err(Error::new_spanned(
+            duplicate,
+            format!("duplicate {} attribute", name),
+        )),
+        None => Ok(attr),
+    }
+}
+
+///
+/// Name of the field in documents, after serde's `rename` and `rename_all`
+///
+fn field_name(input: &DeriveInput, field: &syn::Field) -> Result<String> {
+    if let Some(rename) = serde_arg(&field.attrs, "rename")? {
+        return Ok(rename.value());
+    }
+
+    let ident = field.ident.as_ref().unwrap().to_string();
+    let name = ident.trim_start_matches("r#");
+
+    match serde_arg(&input.attrs, "rename_all")? {
+        Some(rule) => rename_field(&rule, name),
+        None => Ok(name.to_string()),
+    }
+}
+
+///
+/// Value of `#[serde(key = "...")]`, the `key(serialize = "...")` form is
+/// rejected as fields have a single name in documents
+///
+fn serde_arg(attrs: &[Attribute], key: &str) -> Result<Option<LitStr>> {
+    let mut value = None;
+
+    for attr in attrs.iter().filter(|a| a.path.is_ident("serde")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            _ => continue,
+        };
+
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(key) => match nv.lit {
+                    Lit::Str(s) => value = Some(s),
+                    lit => {
+                        return Err(Error::new_spanned(
+                            lit,
+                            format!("`{}` expects a string", key),
+                        ))
+                    }
+                },
+                NestedMeta::Meta(Meta::List(list)) if list.path.is_ident(key) => {
+                    return Err(Error::new_spanned(
+                        list,
+                        format!("expected `{} = \"...\"`, one name in documents", key),
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(value)
+}
+
+///
+/// Applies a serde `rename_all` rule to a snake_case field name
+///
+fn rename_field(rule: &LitStr, name: &str) -> Result<String> {
+    let pascal = || {
+        name.split('_')
+            .map(|word| map_first(word, char::to_ascii_uppercase))
+            .collect::<String>()
+    };
+
+    Ok(match rule.value().as_str() {
+        "lowercase" | "snake_case" => name.to_string(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => name.to_ascii_uppercase(),
+        "PascalCase" => pascal(),
+        "camelCase" => map_first(&pascal(), char::to_ascii_lowercase),
+        "kebab-case" => name.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => name.to_ascii_uppercase().replace('_', "-"),
+        other => {
+            return Err(Error::new(
+                rule.span(),
+                format!("unknown rename_all rule: {}", other),
+            ))
+        }
+    })
+}
+
+fn map_first(word: &str, f: impl Fn(&char) -> char) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => f(&first).to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+///
+/// Parses `#[attr]` and `#[attr(key = "value", ...)]` into key/value pairs
+///
+fn attr_args(attr: &Attribute) -> Result<Vec<(Ident, Lit)>> {
+    match attr.parse_meta()? {
+        Meta::Path(_) => Ok(vec![]),
+        Meta::List(list) => list
+            .nested
+            .into_iter()
+            .map(|nested| match nested {
+                NestedMeta::Meta(Meta::NameValue(nv)) => match nv.path.get_ident() {
+                    Some(ident) => Ok((ident.clone(), nv.lit)),
+                    None => Err(Error::new_spanned(nv.path, "expected identifier")),
+                },
+                other => Err(Error::new_spanned(other, "expected `key = \"value\"`")),
+            })
+            .collect(),
+        Meta::NameValue(nv) => Err(Error::new_spanned(nv, "expected `attr(key = \"value\")`")),
+    }
+}
+
+fn str_value(key: &Ident, value: Lit) -> Result<String> {
+    match value {
+        Lit::Str(s) => Ok(s.value()),
+        _ => Err(Error::new(
+            key.span(),
+            format!("`{}` expects a string", key),
+        )),
+    }
+}
+
+fn filter_op(op: &str, span: Span) -> Result<Ident> {
+    FILTER_OPS
+        .iter()
+        .find(|(name, _)| *name == op)
+        .map(|(_, variant)| Ident::new(variant, span))
+        .ok_or_else(|| Error::new(span, format!("unknown filter operator: {}", op)))
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    fn expanded(result: Result<TokenStream2>) -> String {
+        result.unwrap().to_string()
+    }
+
+    fn error(result: Result<TokenStream2>) -> String {
+        result.err().unwrap().to_string()
+    }
+
+    #[test]
+    fn mongo_collection_names_collection() {
+        let input = parse_quote! {
+            #[mongo(collection = "users")]
+            struct User {}
+        };
+
+        let expected = quote! {
+            impl ::api::MongoCollection for User {
+                fn collection_name() -> &'static str {
+                    "users"
+                }
+            }
+        };
+        assert_eq!(
+            expanded(expand_mongo_collection(&input)),
+            expected.to_string()
+        );
+    }
+
+    #[test]
+    fn mongo_collection_requires_collection() {
+        let input = parse_quote! {
+            struct User {}
+        };
+        assert_eq!(
+            error(expand_mongo_collection(&input)),
+            "missing #[mongo(collection = \"...\")] attribute"
+        );
+    }
+
+    #[test]
+    fn mongo_collection_rejects_unknown_and_non_string_args() {
+        let unknown = parse_quote! {
+            #[mongo(table = "users")]
+            struct User {}
+        };
+        assert_eq!(
+            error(expand_mongo_collection(&unknown)),
+            "unknown mongo attribute"
+        );
+
+        let number = parse_quote! {
+            #[mongo(collection = 1)]
+            struct User {}
+        };
+        assert_eq!(
+            error(expand_mongo_collection(&number)),
+            "`collection` expects a string"
+        );
+    }
+
+    #[test]
+    fn sortable_lists_marked_fields_with_aliases() {
+        let input = parse_quote! {
+            struct User {
+                #[sortable]
+                last_name: String,
+                #[sortable(alias = "_id")]
+                id: String,
+                password: String,
+            }
+        };
+
+        let expected = quote! {
+            impl ::api::Sortable for User {
+                const SORT_FIELDS: &'static [(&'static str, ::std::option::Option<&'static str>)] = &[
+                    ("last_name", ::std::option::Option::None),
+                    ("id", ::std::option::Option::Some("_id"))
+                ];
+            }
+        };
+        assert_eq!(expanded(expand_sortable(&input)), expected.to_string());
+    }
+
+    #[test]
+    fn sortable_rejects_bad_attributes() {
+        let unknown = parse_quote! {
+            struct User {
+                #[sortable(name = "x")]
+                id: String,
+            }
+        };
+        assert_eq!(
+            error(expand_sortable(&unknown)),
+            "unknown sortable attribute"
+        );
+
+        let flag = parse_quote! {
+            struct User {
+                #[sortable(desc)]
+                id: String,
+            }
+        };
+        assert_eq!(error(expand_sortable(&flag)), "expected `key = \"value\"`");
+    }
+
+    #[test]
+    fn sortable_requires_named_struct() {
+        let tuple = parse_quote! {
+            struct User(String);
+        };
+        assert_eq!(error(expand_sortable(&tuple)), "expected named fields");
+
+        let variants = parse_quote! {
+            enum User { A }
+        };
+        assert_eq!(error(expand_sortable(&variants)), "expected a struct");
+    }
+
+    #[test]
+    fn filterable_lists_fields_with_ops() {
+        let input = parse_quote! {
+            struct User {
+                #[filterable]
+                email: String,
+                #[filterable(ops = "eq, in")]
+                last_name: String,
+            }
+        };
+
+        let expected = quote! {
+            impl ::api::Filterable for User {
+                const FILTER_FIELDS: &'static [(&'static str, &'static [::api::fields::FilterOp])] = &[
+                    ("email", ::api::fields::FilterOp::ALL),
+                    ("last_name", &[::api::fields::FilterOp::Eq, ::api::fields::FilterOp::In])
+                ];
+            }
+        };
+        assert_eq!(expanded(expand_filterable(&input)), expected.to_string());
+    }
+
+    #[test]
+    fn filterable_rejects_unknown_ops() {
+        let input = parse_quote! {
+            struct User {
+                #[filterable(ops = "eq, like")]
+                email: String,
+            }
+        };
+        assert_eq!(
+            error(expand_filterable(&input)),
+            "unknown filter operator: like"
+        );
+    }
+
+    #[test]
+    fn projectable_lists_marked_fields() {
+        let input = parse_quote! {
+            struct User {
+                #[projectable]
+                email: String,
+                password: String,
+            }
+        };
+
+        let expected = quote! {
+            impl ::api::Projectable for User {
+                const PROJECTION_FIELDS: &'static [&'static str] = &["email"];
+            }
+        };
+        assert_eq!(expanded(expand_projectable(&input)), expected.to_string());
+    }
+
+    #[test]
+    fn projectable_takes_no_args() {
+        let input = parse_quote! {
+            struct User {
+                #[projectable(alias = "mail")]
+                email: String,
+            }
+        };
+        assert_eq!(
+            error(expand_projectable(&input)),
+            "unknown projectable attribute"
+        );
+    }
+
+    #[test]
+    fn fields_are_named_after_serde_renames() {
+        let input = parse_quote! {
+            #[serde(rename_all = "camelCase")]
+            struct User {
+                #[sortable]
+                last_name: String,
+                #[serde(rename = "_id")]
+                #[sortable(alias = "id")]
+                id: String,
+                #[serde(default)]
+                #[sortable]
+                r#type: String,
+            }
+        };
+
+        let expected = quote! {
+            impl ::api::Sortable for User {
+                const SORT_FIELDS: &'static [(&'static str, ::std::option::Option<&'static str>)] = &[
+                    ("lastName", ::std::option::Option::None),
+                    ("_id", ::std::option::Option::Some("id")),
+                    ("type", ::std::option::Option::None)
+                ];
+            }
+        };
+        assert_eq!(expanded(expand_sortable(&input)), expected.to_string());
+    }
+
+    #[test]
+    fn rename_all_rules_apply_to_field_names() {
+        let rule = |rule: &str| rename_field(&LitStr::new(rule, Span::call_site()), "last_name");
+
+        assert_eq!(rule("lowercase").unwrap(), "last_name");
+        assert_eq!(rule("UPPERCASE").unwrap(), "LAST_NAME");
+        assert_eq!(rule("PascalCase").unwrap(), "LastName");
+        assert_eq!(rule("camelCase").unwrap(), "lastName");
+        assert_eq!(rule("snake_case").unwrap(), "last_name");
+        assert_eq!(rule("SCREAMING_SNAKE_CASE").unwrap(), "LAST_NAME");
+        assert_eq!(rule("kebab-case").unwrap(), "last-name");
+        assert_eq!(rule("SCREAMING-KEBAB-CASE").unwrap(), "LAST-NAME");
+        assert_eq!(
+            rule("Title Case").err().unwrap().to_string(),
+            "unknown rename_all rule: Title Case"
+        );
+    }
+
+    #[test]
+    fn separate_serialize_names_are_rejected() {
+        let input = parse_quote! {
+            struct User {
+                #[serde(rename(serialize = "mail", deserialize = "email"))]
+                #[projectable]
+                email: String,
+            }
+        };
+        assert_eq!(
+            error(expand_projectable(&input)),
+            "expected `rename = \"...\"`, one name in documents"
+        );
+    }
+
+    #[test]
+    fn generics_are_kept() {
+        let input = parse_quote! {
+            #[mongo(collection = "users")]
+            struct User<'a, T: Clone>
+            where
+                T: Send,
+            {
+                #[projectable]
+                #[filterable(ops = "eq")]
+                email: &'a T,
+            }
+        };
+
+        let expected = quote! {
+            impl<'a, T: Clone> ::api::Projectable for User<'a, T>
+            where
+                T: Send,
+            {
+                const PROJECTION_FIELDS: &'static [&'static str] = &["email"];
+            }
+        };
+        assert_eq!(expanded(expand_projectable(&input)), expected.to_string());
+
+        let expanded = expanded(expand_mongo_collection(&input));
+        assert!(expanded.starts_with(
+            &quote!(impl<'a, T: Clone> ::api::MongoCollection for User<'a, T> where T: Send,)
+                .to_string()
+        ));
+        assert!(expand_filterable(&input)
+            .unwrap()
+            .to_string()
+            .contains(&quote!(for User<'a, T>).to_string()));
+    }
+
+    #[test]
+    fn repeated_attributes_are_rejected() {
+        let sortable = parse_quote! {
+            struct User {
+                #[sortable]
+                #[sortable(alias = "ln")]
+                last_name: String,
+            }
+        };
+        assert_eq!(
+            error(expand_sortable(&sortable)),
+            "duplicate sortable attribute"
+        );
+
+        let filterable = parse_quote! {
+            struct User {
+                #[filterable]
+                #[filterable(ops = "eq")]
+                email: String,
+            }
+        };
+        assert_eq!(
+            error(expand_filterable(&filterable)),
+            "duplicate filterable attribute"
+        );
+    }
+}
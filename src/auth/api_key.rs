@@ -0,0 +1,307 @@
+use std::rc::Rc;
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::StatusCode,
+    web, Error, HttpMessage,
+};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use mongodb::{
+    bson::{doc, DateTime},
+    Database,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use super::{errs, AuthenticatedUser, Claims};
+use crate::{models::ApiKey, ErrorCode, MongoCollection, RequestError};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+const KEY_TAG: &str = "ak";
+const PREFIX_LEN: usize = 8;
+const SECRET_LEN: usize = 32;
+
+///
+/// GeneratedKey
+///
+/// A new key in the `ak_<prefix>_<secret>` format. `key` is only
+/// returned to its creator, `prefix` and `hash` are what gets stored.
+///
+pub struct GeneratedKey {
+    pub key: String,
+    pub prefix: String,
+    pub hash: String,
+}
+
+impl GeneratedKey {
+    pub fn generate() -> Self {
+        let prefix = random_string(PREFIX_LEN);
+        let key = format!("{}_{}_{}", KEY_TAG, prefix, random_string(SECRET_LEN));
+
+        Self {
+            hash: hash_key(&key),
+            prefix,
+            key,
+        }
+    }
+}
+
+pub fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+///
+/// True if `key` is the plain value of `api_key` and it is unexpired at `now`
+///
+pub fn verify(api_key: &ApiKey, key: &str, now: DateTime) -> bool {
+    hash_matches(&api_key.hash, key) && !matches!(api_key.expires, Some(expires) if expires <= now)
+}
+
+///
+/// Compares the stored digest with the digest of `key` in constant time
+///
+fn hash_matches(hash: &str, key: &str) -> bool {
+    hash.as_bytes().ct_eq(hash_key(key).as_bytes()).into()
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+///
+/// Reads the lookup prefix from a key in the `ak_<prefix>_<secret>` format
+///
+fn key_prefix(key: &str) -> Option<&str> {
+    let mut parts = key.splitn(3, '_');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(KEY_TAG), Some(prefix), Some(secret))
+            if prefix.len() == PREFIX_LEN && !secret.is_empty() =>
+        {
+            Some(prefix)
+        }
+        _ => None,
+    }
+}
+
+impl From<&ApiKey> for Claims {
+    fn from(api_key: &ApiKey) -> Self {
+        let mut extra = Map::new();
+        extra.insert("scope".into(), Value::String(api_key.scopes.join(" ")));
+        if let Some(id) = api_key.id {
+            extra.insert("api_key".into(), Value::String(id.to_hex()));
+        }
+
+        Claims {
+            sub: api_key.owner.clone(),
+            iss: None,
+            aud: None,
+            exp: api_key
+                .expires
+                .map(|expires| (expires.timestamp_millis() / 1000) as u64),
+            iat: Some((api_key.created.timestamp_millis() / 1000) as u64),
+            extra,
+        }
+    }
+}
+
+///
+/// ApiKeyAuth Middleware
+///
+/// Authenticates `X-Api-Key` headers against the `api_keys` collection
+/// and inserts the same `AuthenticatedUser<Claims>` as `JwtAuth`, with the
+/// key owner as subject and the key's scopes. Requests without the header
+/// pass through unauthenticated.
+///
+pub struct ApiKeyAuth;
+
+impl<S, B> Transform<S, ServiceRequest> for ApiKeyAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = ApiKeyAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ApiKeyAuthMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+pub struct ApiKeyAuthMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiKeyAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let key = match req.headers().get(API_KEY_HEADER) {
+                Some(key) => key
+                    .to_str()
+                    .map_err(|_| errs::invalid_api_key())?
+                    .to_string(),
+                None => return service.call(req).await,
+            };
+
+            let db = req
+                .app_data::<web::Data<Database>>()
+                .cloned()
+                .ok_or_else(|| {
+                    RequestError::builder()
+                        .code(StatusCode::INTERNAL_SERVER_ERROR)
+                        .error(ErrorCode::InternalServerError)
+                        .message("API key authentication requires a database")
+                        .build()
+                })?;
+
+            let api_key = authenticate(&db, &key).await?;
+            req.extensions_mut()
+                .insert(AuthenticatedUser(Claims::from(&api_key)));
+
+            service.call(req).await
+        })
+    }
+}
+
+///
+/// Looks up an unrevoked, unexpired key and records its use
+///
+async fn authenticate(db: &web::Data<Database>, key: &str) -> Result<ApiKey, RequestError> {
+    let prefix = key_prefix(key).ok_or_else(errs::invalid_api_key)?;
+
+    let now = DateTime::now();
+    let api_key = ApiKey::collection::<ApiKey>(db)
+        .find_one(doc! { "prefix": prefix, "revoked": null }, None)
+        .await?
+        .filter(|api_key| verify(api_key, key, now))
+        .ok_or_else(errs::invalid_api_key)?;
+
+    ApiKey::collection::<ApiKey>(db)
+        .update_one(
+            doc! { "_id": api_key.id, "prefix": prefix },
+            doc! { "$set": { "last_used": now } },
+            None,
+        )
+        .await?;
+
+    Ok(api_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        test::{init_service, TestRequest},
+        App, HttpRequest, HttpResponse,
+    };
+    use mongodb::Client;
+
+    use super::*;
+
+    async fn whoami(req: HttpRequest) -> HttpResponse {
+        match req.extensions().get::<AuthenticatedUser<Claims>>() {
+            Some(user) => HttpResponse::Ok().body(user.sub.clone()),
+            None => HttpResponse::NoContent().finish(),
+        }
+    }
+
+    async fn call(key: Option<&str>) -> Result<ServiceResponse, Error> {
+        let db = Client::with_uri_str("mongodb://127.0.0.1:1")
+            .await
+            .unwrap()
+            .database("test");
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(db))
+                .wrap(ApiKeyAuth)
+                .route("/", web::get().to(whoami)),
+        )
+        .await;
+
+        let mut req = TestRequest::get().uri("/");
+        if let Some(key) = key {
+            req = req.insert_header((API_KEY_HEADER, key));
+        }
+        app.call(req.to_request()).await
+    }
+
+    #[actix_web::test]
+    async fn request_without_key_passes_unauthenticated() {
+        let resp = call(None).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[actix_web::test]
+    async fn malformed_key_is_unauthorized() {
+        for key in [
+            "nope",
+            "ak_short_secret",
+            "xx_abcdefgh_secret",
+            "ak_abcdefgh_",
+        ] {
+            let error = call(Some(key)).await.err().unwrap();
+            let status = error.as_response_error().status_code();
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", key);
+        }
+    }
+
+    #[test]
+    fn expired_key_does_not_verify() {
+        let generated = GeneratedKey::generate();
+        let now = DateTime::now();
+        let mut api_key = ApiKey {
+            id: None,
+            name: "ci".into(),
+            prefix: generated.prefix,
+            hash: generated.hash,
+            owner: "owner".into(),
+            scopes: vec![],
+            created: now,
+            expires: None,
+            last_used: None,
+            revoked: None,
+        };
+        assert!(verify(&api_key, &generated.key, now));
+
+        api_key.expires = Some(now);
+        assert!(!verify(&api_key, &generated.key, now));
+    }
+
+    #[test]
+    fn generated_key_matches_its_hash() {
+        let generated = GeneratedKey::generate();
+        assert_eq!(key_prefix(&generated.key), Some(generated.prefix.as_str()));
+        assert!(hash_matches(&generated.hash, &generated.key));
+    }
+
+    #[test]
+    fn other_key_does_not_match() {
+        let generated = GeneratedKey::generate();
+        let other = GeneratedKey::generate();
+        assert!(!hash_matches(&generated.hash, &other.key));
+        assert!(!hash_matches("", &generated.key));
+    }
+}
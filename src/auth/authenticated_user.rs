@@ -0,0 +1,45 @@
+use std::ops::Deref;
+
+use actix_web::{dev::Payload, FromRequest, HttpMessage, HttpRequest};
+use futures::future::{err, ok, Ready};
+
+use super::{errs, Claims};
+use crate::RequestError;
+
+///
+/// AuthenticatedUser
+///
+/// Typed claims of the bearer token, inserted into request extensions
+/// by `JwtAuth`. Extraction fails with 401 when no token was provided.
+///
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser<C = Claims>(pub C);
+
+impl<C> AuthenticatedUser<C> {
+    pub fn into_inner(self) -> C {
+        self.0
+    }
+}
+
+impl<C> Deref for AuthenticatedUser<C> {
+    type Target = C;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<C> FromRequest for AuthenticatedUser<C>
+where
+    C: Clone + 'static,
+{
+    type Error = RequestError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        match req.extensions().get::<AuthenticatedUser<C>>() {
+            Some(user) => ok(user.clone()),
+            None => err(errs::unauthorized()),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+///
+/// Claims
+///
+/// Registered JWT claims, with every other claim kept in `extra`
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub iss: Option<String>,
+    pub aud: Option<Value>,
+    pub exp: Option<u64>,
+    pub iat: Option<u64>,
+
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Claims {
+    ///
+    /// Granted scopes from the space delimited `scope` claim or the `scp` claim
+    ///
+    pub fn scopes(&self) -> Vec<&str> {
+        let mut scopes = vec![];
+
+        for claim in ["scope", "scp"] {
+            match self.extra.get(claim) {
+                Some(Value::String(s)) => scopes.extend(s.split_whitespace()),
+                Some(Value::Array(a)) => scopes.extend(a.iter().filter_map(Value::as_str)),
+                _ => {}
+            }
+        }
+
+        scopes
+    }
+
+    pub fn roles(&self) -> Vec<&str> {
+        match self.extra.get("roles") {
+            Some(Value::String(s)) => vec![s.as_str()],
+            Some(Value::Array(a)) => a.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        }
+    }
+
+    pub fn email(&self) -> Option<&str> {
+        self.extra.get("email").and_then(Value::as_str)
+    }
+
+    ///
+    /// The `email` claim, only when `email_verified` is `true`
+    ///
+    pub fn verified_email(&self) -> Option<&str> {
+        match self.extra.get("email_verified") {
+            Some(Value::Bool(true)) => self.email(),
+            _ => None,
+        }
+    }
+}
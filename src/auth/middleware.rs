@@ -0,0 +1,283 @@
+use std::{marker::PhantomData, rc::Rc, sync::Arc};
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::AUTHORIZATION,
+    Error, HttpMessage,
+};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use serde::de::DeserializeOwned;
+
+use super::{errs, AuthenticatedUser, Claims};
+use crate::{
+    utils::jwt::{Decoder, JwtDecoder},
+    RequestError,
+};
+
+///
+/// JwtAuth Middleware
+///
+/// Decodes `Authorization: Bearer` tokens with the configured decoder and
+/// inserts `AuthenticatedUser<C>` into request extensions. Requests without
+/// an `Authorization` header, or any request when no decoder is configured,
+/// pass through unauthenticated.
+///
+pub struct JwtAuth<D, C = Claims>
+where
+    D: Decoder,
+{
+    decoder: Option<Arc<JwtDecoder<D>>>,
+    claims: PhantomData<C>,
+}
+
+impl<D, C> JwtAuth<D, C>
+where
+    D: Decoder,
+{
+    pub fn new(decoder: impl Into<Arc<JwtDecoder<D>>>) -> Self {
+        Self::optional(Some(decoder.into()))
+    }
+
+    pub fn optional(decoder: Option<Arc<JwtDecoder<D>>>) -> Self {
+        Self {
+            decoder,
+            claims: PhantomData,
+        }
+    }
+}
+
+impl<S, B, D, C> Transform<S, ServiceRequest> for JwtAuth<D, C>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+    D: Decoder + 'static,
+    C: DeserializeOwned + Clone + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = JwtAuthMiddleware<S, D, C>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(JwtAuthMiddleware {
+            service: Rc::new(service),
+            decoder: self.decoder.clone(),
+            claims: PhantomData,
+        })
+    }
+}
+
+pub struct JwtAuthMiddleware<S, D, C>
+where
+    D: Decoder,
+{
+    service: Rc<S>,
+    decoder: Option<Arc<JwtDecoder<D>>>,
+    claims: PhantomData<C>,
+}
+
+impl<S, B, D, C> Service<ServiceRequest> for JwtAuthMiddleware<S, D, C>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+    D: Decoder + 'static,
+    C: DeserializeOwned + Clone + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let decoder = self.decoder.clone();
+
+        Box::pin(async move {
+            let decoder = match decoder {
+                Some(decoder) => decoder,
+                None => return service.call(req).await,
+            };
+
+            if let Some(token) = bearer_token(&req)? {
+                let data = decoder
+                    .decode::<C>(&token)
+                    .await
+                    .map_err(RequestError::from)?;
+
+                req.extensions_mut().insert(AuthenticatedUser(data.claims));
+            }
+
+            service.call(req).await
+        })
+    }
+}
+
+///
+/// Reads the token from an `Authorization: Bearer <token>` header
+///
+fn bearer_token(req: &ServiceRequest) -> Result<Option<String>, RequestError> {
+    let header = match req.headers().get(AUTHORIZATION) {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+
+    let value = header
+        .to_str()
+        .map_err(|_| errs::invalid_request("Invalid authorization header"))?;
+
+    match value.split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") && !token.is_empty() => {
+            Ok(Some(token.trim().to_string()))
+        }
+        _ => Err(errs::invalid_request("Expected bearer authorization")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::StatusCode,
+        test::{init_service, TestRequest},
+        web, App, HttpRequest, HttpResponse,
+    };
+    use async_trait::async_trait;
+    use jsonwebtoken::{DecodingKey, EncodingKey, Header, TokenData, Validation};
+    use serde_json::json;
+
+    use super::*;
+    use crate::utils::{jwt::DecodeInfo, oauth};
+
+    const SECRET: &[u8] = b"middleware-test-secret";
+
+    struct Hs256;
+
+    #[async_trait(?Send)]
+    impl Decoder for Hs256 {
+        async fn decode<T>(&self, dec_info: &DecodeInfo<'_>) -> Result<TokenData<T>, oauth::Error>
+        where
+            T: DeserializeOwned,
+        {
+            let key = DecodingKey::from_secret(SECRET);
+            Ok(jsonwebtoken::decode(
+                dec_info.token,
+                &key,
+                dec_info.validation,
+            )?)
+        }
+    }
+
+    fn signed(secret: &[u8], sub: &str) -> String {
+        let exp = jsonwebtoken::get_current_timestamp() + 60;
+        let claims = json!({ "sub": sub, "exp": exp });
+        jsonwebtoken::encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret),
+        )
+        .unwrap()
+    }
+
+    fn token(sub: &str) -> String {
+        signed(SECRET, sub)
+    }
+
+    async fn whoami(req: HttpRequest) -> HttpResponse {
+        match req.extensions().get::<AuthenticatedUser<Claims>>() {
+            Some(user) => HttpResponse::Ok().body(user.sub.clone()),
+            None => HttpResponse::NoContent().finish(),
+        }
+    }
+
+    async fn call(authorization: Option<&str>) -> Result<ServiceResponse, Error> {
+        let decoder = JwtDecoder::new(Hs256, Validation::default());
+        let app = init_service(
+            App::new()
+                .wrap(JwtAuth::<_, Claims>::new(decoder))
+                .route("/", web::get().to(whoami)),
+        )
+        .await;
+
+        let mut req = TestRequest::get().uri("/");
+        if let Some(authorization) = authorization {
+            req = req.insert_header((AUTHORIZATION, authorization));
+        }
+        app.call(req.to_request()).await
+    }
+
+    fn status(result: Result<ServiceResponse, Error>) -> StatusCode {
+        match result {
+            Ok(resp) => resp.status(),
+            Err(error) => error.as_response_error().status_code(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn request_without_header_passes_unauthenticated() {
+        assert_eq!(status(call(None).await), StatusCode::NO_CONTENT);
+    }
+
+    #[actix_web::test]
+    async fn valid_token_authenticates_the_request() {
+        let resp = call(Some(&format!("Bearer {}", token("ada"))))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(body, "ada");
+    }
+
+    #[actix_web::test]
+    async fn scheme_is_case_insensitive() {
+        let resp = call(Some(&format!("bearer {}", token("ada")))).await;
+        assert_eq!(status(resp), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn malformed_header_is_unauthorized() {
+        for header in ["Bearer", "Bearer ", "garbage"] {
+            assert_eq!(
+                status(call(Some(header)).await),
+                StatusCode::UNAUTHORIZED,
+                "{}",
+                header
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn other_scheme_is_unauthorized() {
+        let resp = call(Some(&format!("Basic {}", token("ada")))).await;
+        assert_eq!(status(resp), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn invalid_token_is_unauthorized() {
+        assert_eq!(
+            status(call(Some("Bearer not.a.jwt")).await),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let other = signed(b"another-secret", "ada");
+        let resp = call(Some(&format!("Bearer {}", other))).await;
+        assert_eq!(status(resp), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn request_passes_through_without_decoder() {
+        let app = init_service(
+            App::new()
+                .wrap(JwtAuth::<Hs256, Claims>::optional(None))
+                .route("/", web::get().to(whoami)),
+        )
+        .await;
+        let req = TestRequest::get()
+            .uri("/")
+            .insert_header((AUTHORIZATION, "Bearer whatever"))
+            .to_request();
+        let resp = app.call(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    }
+}
@@ -0,0 +1,96 @@
+pub mod api_key;
+mod authenticated_user;
+mod claims;
+mod middleware;
+mod require;
+
+pub use api_key::{ApiKeyAuth, ApiKeyAuthMiddleware};
+pub use authenticated_user::AuthenticatedUser;
+pub use claims::Claims;
+pub use middleware::{JwtAuth, JwtAuthMiddleware};
+pub use require::{IsSelf, Or, Require, RoleList, Roles, Rule, ScopeList, Scopes, SelfOr};
+
+pub(crate) mod errs {
+    use actix_web::http::{
+        header::{HeaderValue, WWW_AUTHENTICATE},
+        StatusCode,
+    };
+
+    use serde_json::json;
+
+    use crate::{ErrorCode, RequestError};
+
+    fn www_authenticate(error: Option<&str>, description: &str) -> HeaderValue {
+        let value = match error {
+            Some(error) => format!(
+                "Bearer error=\"{}\", error_description=\"{}\"",
+                error, description
+            ),
+            None => "Bearer".to_string(),
+        };
+
+        HeaderValue::from_str(&value).unwrap_or_else(|_| HeaderValue::from_static("Bearer"))
+    }
+
+    /// No credentials were provided
+    pub fn unauthorized() -> RequestError {
+        RequestError::builder()
+            .code(StatusCode::UNAUTHORIZED)
+            .error(ErrorCode::Unauthorized)
+            .message("Authentication required")
+            .header(WWW_AUTHENTICATE, www_authenticate(None, ""))
+            .build()
+    }
+
+    /// X-Api-Key header is malformed, unknown, revoked or expired
+    pub fn invalid_api_key() -> RequestError {
+        RequestError::builder()
+            .code(StatusCode::UNAUTHORIZED)
+            .error(ErrorCode::Unauthorized)
+            .message("Invalid API key")
+            .build()
+    }
+
+    /// Authorization header is not a well formed bearer token
+    pub fn invalid_request(description: &str) -> RequestError {
+        RequestError::builder()
+            .code(StatusCode::UNAUTHORIZED)
+            .error(ErrorCode::Unauthorized)
+            .message(description)
+            .header(
+                WWW_AUTHENTICATE,
+                www_authenticate(Some("invalid_request"), description),
+            )
+            .build()
+    }
+
+    /// Bearer token failed to decode or validate
+    pub fn invalid_token(description: &str) -> RequestError {
+        RequestError::builder()
+            .code(StatusCode::UNAUTHORIZED)
+            .error(ErrorCode::Unauthorized)
+            .message("Invalid bearer token")
+            .detail(Some(description.into()))
+            .header(
+                WWW_AUTHENTICATE,
+                www_authenticate(Some("invalid_token"), description),
+            )
+            .build()
+    }
+
+    /// Token is valid but lacks the permissions for the request
+    pub fn insufficient_scope(missing: Vec<String>) -> RequestError {
+        let description = "Insufficient permissions";
+
+        RequestError::builder()
+            .code(StatusCode::FORBIDDEN)
+            .error(ErrorCode::Forbidden)
+            .message(description)
+            .detail(Some(json!({ "missing": missing })))
+            .header(
+                WWW_AUTHENTICATE,
+                www_authenticate(Some("insufficient_scope"), description),
+            )
+            .build()
+    }
+}
@@ -0,0 +1,312 @@
+use std::{marker::PhantomData, ops::Deref};
+
+use actix_web::{dev::Payload, FromRequest, HttpMessage, HttpRequest};
+use futures::future::{err, ok, Ready};
+
+use super::{errs, AuthenticatedUser, Claims};
+use crate::{
+    fields::{EmailOrObjectId, FromPath},
+    RequestError,
+};
+
+///
+/// Authorization rule checked against the decoded claims
+///
+pub trait Rule {
+    /// Returns the permissions the claims are missing, empty when allowed
+    fn missing(req: &HttpRequest, claims: &Claims) -> Vec<String>;
+}
+
+pub trait ScopeList {
+    const SCOPES: &'static [&'static str];
+}
+
+pub trait RoleList {
+    const ROLES: &'static [&'static str];
+}
+
+/// Requires every scope in `S`
+pub struct Scopes<S>(PhantomData<S>);
+
+impl<S: ScopeList> Rule for Scopes<S> {
+    fn missing(_: &HttpRequest, claims: &Claims) -> Vec<String> {
+        let scopes = claims.scopes();
+
+        S::SCOPES
+            .iter()
+            .filter(|scope| !scopes.contains(scope))
+            .map(|scope| format!("scope:{}", scope))
+            .collect()
+    }
+}
+
+/// Requires every role in `R`
+pub struct Roles<R>(PhantomData<R>);
+
+impl<R: RoleList> Rule for Roles<R> {
+    fn missing(_: &HttpRequest, claims: &Claims) -> Vec<String> {
+        let roles = claims.roles();
+
+        R::ROLES
+            .iter()
+            .filter(|role| !roles.contains(role))
+            .map(|role| format!("role:{}", role))
+            .collect()
+    }
+}
+
+/// Allows requests whose `{id}` path segment identifies the token subject,
+/// by its id or its verified email
+pub struct IsSelf;
+
+impl Rule for IsSelf {
+    fn missing(req: &HttpRequest, claims: &Claims) -> Vec<String> {
+        let is_self = req
+            .match_info()
+            .get("id")
+            .and_then(|id| EmailOrObjectId::from_path("id", id).ok())
+            .map(|id| match id {
+                EmailOrObjectId::ObjectId(ref oid) => claims.sub == oid.to_hex(),
+                EmailOrObjectId::Email(ref email) => claims
+                    .verified_email()
+                    .is_some_and(|verified| verified.eq_ignore_ascii_case(email)),
+            })
+            .unwrap_or(false);
+
+        if is_self {
+            vec![]
+        } else {
+            vec!["self".to_string()]
+        }
+    }
+}
+
+/// Allows requests satisfying either `A` or `B`
+pub struct Or<A, B>(PhantomData<(A, B)>);
+
+impl<A: Rule, B: Rule> Rule for Or<A, B> {
+    fn missing(req: &HttpRequest, claims: &Claims) -> Vec<String> {
+        let missing = A::missing(req, claims);
+        if missing.is_empty() {
+            return missing;
+        }
+
+        let other = B::missing(req, claims);
+        if other.is_empty() {
+            return other;
+        }
+
+        missing.into_iter().chain(other).collect()
+    }
+}
+
+/// The token subject may act on itself, otherwise `R` applies
+pub type SelfOr<R> = Or<IsSelf, R>;
+
+///
+/// Require
+///
+/// Extracts the authenticated user, failing with 401 when unauthenticated
+/// and 403 listing the missing permissions when `R` is not satisfied.
+///
+pub struct Require<R: Rule> {
+    pub user: AuthenticatedUser<Claims>,
+    rule: PhantomData<R>,
+}
+
+impl<R: Rule> Deref for Require<R> {
+    type Target = Claims;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+impl<R: Rule> FromRequest for Require<R> {
+    type Error = RequestError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let user = match req.extensions().get::<AuthenticatedUser<Claims>>() {
+            Some(user) => user.clone(),
+            None => return err(errs::unauthorized()),
+        };
+
+        let missing = R::missing(req, &user);
+        if !missing.is_empty() {
+            return err(errs::insufficient_scope(missing));
+        }
+
+        ok(Require {
+            user,
+            rule: PhantomData,
+        })
+    }
+}
+
+///
+/// Declares a `ScopeList` marker type, e.g. `scopes!(UsersWrite => ["users:write"])`
+///
+#[macro_export]
+macro_rules! scopes {
+    ($name:ident => [$($scope:expr),+ $(,)?]) => {
+        pub struct $name;
+
+        impl $crate::auth::ScopeList for $name {
+            const SCOPES: &'static [&'static str] = &[$($scope),+];
+        }
+    };
+}
+
+///
+/// Declares a `RoleList` marker type, e.g. `roles!(Admin => ["admin"])`
+///
+#[macro_export]
+macro_rules! roles {
+    ($name:ident => [$($role:expr),+ $(,)?]) => {
+        pub struct $name;
+
+        impl $crate::auth::RoleList for $name {
+            const ROLES: &'static [&'static str] = &[$($role),+];
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use serde_json::{json, Map, Value};
+
+    use super::*;
+
+    crate::scopes!(UsersRead => ["users:read"]);
+
+    const SUBJECT: &str = "62f1b2c3d4e5f6a7b8c9d0e1";
+
+    fn claims(scope: &str) -> Claims {
+        let mut extra = Map::new();
+        extra.insert("scope".into(), Value::String(scope.into()));
+
+        Claims {
+            sub: SUBJECT.into(),
+            iss: None,
+            aud: None,
+            exp: None,
+            iat: None,
+            extra,
+        }
+    }
+
+    fn claims_with_email(email: &str, verified: Value) -> Claims {
+        let mut claims = claims("");
+        claims.extra.insert("email".into(), email.into());
+        claims.extra.insert("email_verified".into(), verified);
+        claims
+    }
+
+    async fn require<R: Rule>(
+        request: TestRequest,
+        claims: Option<Claims>,
+    ) -> Result<Require<R>, RequestError> {
+        let (req, mut payload) = request.to_http_parts();
+        if let Some(claims) = claims {
+            req.extensions_mut().insert(AuthenticatedUser(claims));
+        }
+        Require::<R>::from_request(&req, &mut payload).await
+    }
+
+    #[actix_web::test]
+    async fn unauthenticated_is_unauthorized() {
+        let error = require::<Scopes<UsersRead>>(TestRequest::default(), None)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.code, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn granted_scope_is_allowed() {
+        let required =
+            require::<Scopes<UsersRead>>(TestRequest::default(), Some(claims("users:read"))).await;
+        assert!(required.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn missing_scope_is_forbidden() {
+        let error = require::<Scopes<UsersRead>>(TestRequest::default(), Some(claims("other")))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.code, StatusCode::FORBIDDEN);
+        assert_eq!(
+            error.detail.unwrap()["missing"],
+            json!(["scope:users:read"])
+        );
+    }
+
+    #[actix_web::test]
+    async fn self_subject_is_allowed_without_scope() {
+        let request = TestRequest::default().param("id", SUBJECT);
+        let required = require::<SelfOr<Scopes<UsersRead>>>(request, Some(claims(""))).await;
+        assert!(required.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn other_subject_without_scope_is_forbidden() {
+        let request = TestRequest::default().param("id", "62f1b2c3d4e5f6a7b8c9d0ff");
+        let error = require::<SelfOr<Scopes<UsersRead>>>(request, Some(claims("")))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.code, StatusCode::FORBIDDEN);
+        assert_eq!(
+            error.detail.unwrap()["missing"],
+            json!(["self", "scope:users:read"])
+        );
+    }
+
+    #[actix_web::test]
+    async fn other_subject_with_scope_is_allowed() {
+        let request = TestRequest::default().param("id", "62f1b2c3d4e5f6a7b8c9d0ff");
+        let required =
+            require::<SelfOr<Scopes<UsersRead>>>(request, Some(claims("users:read"))).await;
+        assert!(required.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn verified_email_is_self_in_any_case() {
+        for id in ["ada@example.com", "Ada@Example.COM"] {
+            let request = TestRequest::default().param("id", id);
+            let claims = claims_with_email("ada@example.com", json!(true));
+            let required = require::<SelfOr<Scopes<UsersRead>>>(request, Some(claims)).await;
+            assert!(required.is_ok(), "{}", id);
+        }
+    }
+
+    #[actix_web::test]
+    async fn unverified_email_is_not_self() {
+        for verified in [json!(false), json!("true"), Value::Null] {
+            let request = TestRequest::default().param("id", "ada@example.com");
+            let claims = claims_with_email("ada@example.com", verified.clone());
+            let error = require::<SelfOr<Scopes<UsersRead>>>(request, Some(claims))
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(error.code, StatusCode::FORBIDDEN, "{}", verified);
+        }
+
+        let request = TestRequest::default().param("id", "ada@example.com");
+        let mut claims = claims_with_email("ada@example.com", json!(true));
+        claims.extra.remove("email_verified");
+        let required = require::<SelfOr<Scopes<UsersRead>>>(request, Some(claims)).await;
+        assert!(required.is_err());
+    }
+
+    #[actix_web::test]
+    async fn other_email_is_not_self() {
+        let request = TestRequest::default().param("id", "grace@example.com");
+        let claims = claims_with_email("ada@example.com", json!(true));
+        let required = require::<SelfOr<Scopes<UsersRead>>>(request, Some(claims)).await;
+        assert!(required.is_err());
+    }
+}
@@ -0,0 +1,386 @@
+use actix_web::{http::StatusCode, web, HttpResponse, Responder};
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
+    Database,
+};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{
+    auth::{api_key::GeneratedKey, Claims, Require, Scopes},
+    error::ErrorCode,
+    fields::PageCursor,
+    models::ApiKey,
+    schemas::{ApiKeyOut, Page, PageBuilder, PageParams},
+    web::{Json, Query, ValidPath},
+    MongoCollection, RequestError, RequestResult,
+};
+
+///
+/// List Api Keys of the Caller
+///
+pub async fn get_api_keys(
+    auth: Require<Scopes<perms::ApiKeysRead>>,
+    query: Query<PageParams>,
+    db: web::Data<Database>,
+) -> RequestResult<impl Responder> {
+    let filter = doc! { "owner": &auth.sub };
+    let sort = PageCursor::keyset_sort(None);
+
+    let cursor = ApiKey::collection(&db)
+        .find(
+            query.mongo_filter(Some(filter.clone()), &sort)?,
+            FindOptions::builder()
+                .limit(query.limit)
+                .skip(query.skip())
+                .sort(sort.clone())
+                .build(),
+        )
+        .await?;
+
+    let total = if query.counts() {
+        let total = ApiKey::collection::<ApiKey>(&db)
+            .count_documents(filter, None)
+            .await?;
+        Some(total)
+    } else {
+        None
+    };
+
+    let page: Page<ApiKey> = PageBuilder::from(&*query)
+        .total(total)
+        .sort(sort)
+        .build(cursor)
+        .await?;
+    Ok(web::Json(page.map(ApiKeyOut::from)))
+}
+
+///
+/// Create Api Key
+///
+/// The plain key is only part of this response
+///
+pub async fn create_api_key(
+    auth: Require<Scopes<perms::ApiKeysWrite>>,
+    db: web::Data<Database>,
+    body: Json<body::ApiKeyBody>,
+) -> RequestResult<impl Responder> {
+    let (mut api_key, key) = issue(&auth, &body, DateTime::now())?;
+
+    let result = ApiKey::collection::<ApiKey>(&db)
+        .insert_one(&api_key, None)
+        .await?;
+    api_key.id = result.inserted_id.as_object_id();
+
+    let mut out = ApiKeyOut::from(api_key);
+    out.key = Some(key);
+    Ok(HttpResponse::Created().json(out))
+}
+
+///
+/// Builds the key requested by `claims` and its plain value. Keys cannot
+/// grant more than their owner holds nor outlive the credentials used to
+/// create them.
+///
+fn issue(
+    claims: &Claims,
+    body: &body::ApiKeyBody,
+    now: DateTime,
+) -> Result<(ApiKey, String), RequestError> {
+    let granted = claims.scopes();
+    let missing: Vec<String> = body
+        .scopes
+        .iter()
+        .filter(|scope| !granted.contains(&scope.as_str()))
+        .map(|scope| format!("scope:{}", scope))
+        .collect();
+    if !missing.is_empty() {
+        return Err(errs::scopes_not_granted(missing));
+    }
+
+    let expires = match body.expires_in {
+        Some(secs) => Some(expires_at(now, secs).ok_or_else(errs::invalid_expires_in)?),
+        None => None,
+    };
+
+    //== capped at the expiry of the caller's token or key
+    let caller_expires = claims.exp.map(|exp| {
+        DateTime::from_millis(i64::try_from(exp.saturating_mul(1000)).unwrap_or(i64::MAX))
+    });
+    let expires = match (expires, caller_expires) {
+        (None, Some(_)) => return Err(errs::expires_in_required()),
+        (Some(expires), Some(caller_expires)) => Some(expires.min(caller_expires)),
+        (expires, None) => expires,
+    };
+
+    let generated = GeneratedKey::generate();
+    let api_key = ApiKey {
+        id: None,
+        name: body.name.clone(),
+        prefix: generated.prefix,
+        hash: generated.hash,
+        owner: claims.sub.clone(),
+        scopes: body.scopes.clone(),
+        created: now,
+        expires,
+        last_used: None,
+        revoked: None,
+    };
+
+    Ok((api_key, generated.key))
+}
+
+///
+/// Expiry `secs` after `now`, `None` when it overflows
+///
+fn expires_at(now: DateTime, secs: i64) -> Option<DateTime> {
+    secs.checked_mul(1000)
+        .and_then(|millis| now.timestamp_millis().checked_add(millis))
+        .map(DateTime::from_millis)
+}
+
+///
+/// Revoke Api Key of the Caller
+///
+pub async fn revoke_api_key(
+    auth: Require<Scopes<perms::ApiKeysWrite>>,
+    id: ValidPath<ObjectId>,
+    db: web::Data<Database>,
+) -> RequestResult<impl Responder> {
+    let api_key = ApiKey::collection::<ApiKey>(&db)
+        .find_one_and_update(
+            doc! { "_id": *id, "owner": &auth.sub, "revoked": null },
+            doc! { "$set": { "revoked": DateTime::now() } },
+            FindOneAndUpdateOptions::builder()
+                .return_document(ReturnDocument::After)
+                .build(),
+        )
+        .await?;
+
+    let api_key = api_key.ok_or_else(errs::api_key_not_found)?;
+    Ok(web::Json(ApiKeyOut::from(api_key)))
+}
+
+mod perms {
+    crate::scopes!(ApiKeysRead => ["api_keys:read"]);
+    crate::scopes!(ApiKeysWrite => ["api_keys:write"]);
+}
+
+mod errs {
+    use super::*;
+
+    pub fn api_key_not_found() -> RequestError {
+        RequestError::builder()
+            .code(StatusCode::NOT_FOUND)
+            .error(ErrorCode::ResourceNotFound)
+            .message("Api key not found")
+            .build()
+    }
+
+    pub fn invalid_expires_in() -> RequestError {
+        RequestError::builder()
+            .error(ErrorCode::ValidationError)
+            .message("Api key lifetime is out of range")
+            .detail(Some(serde_json::json!({ "field": "expires_in" })))
+            .build()
+    }
+
+    pub fn expires_in_required() -> RequestError {
+        RequestError::builder()
+            .error(ErrorCode::ValidationError)
+            .message("Api key lifetime is required when the caller's credentials expire")
+            .detail(Some(serde_json::json!({ "field": "expires_in" })))
+            .build()
+    }
+
+    pub fn scopes_not_granted(missing: Vec<String>) -> RequestError {
+        RequestError::builder()
+            .code(StatusCode::FORBIDDEN)
+            .error(ErrorCode::Forbidden)
+            .message("Api key scopes must be granted to the caller")
+            .detail(Some(serde_json::json!({ "missing": missing })))
+            .build()
+    }
+}
+
+mod body {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Validate)]
+    #[serde(deny_unknown_fields)]
+    pub struct ApiKeyBody {
+        #[validate(length(min = 1, max = 100))]
+        pub name: String,
+
+        #[serde(default)]
+        pub scopes: Vec<String>,
+
+        /// Lifetime in seconds, at most ten years, keys without one never
+        /// expire
+        #[validate(range(min = 60, max = 315360000))]
+        pub expires_in: Option<i64>,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        dev::Service,
+        test::{call_service, init_service, TestRequest},
+        App, HttpMessage,
+    };
+    use mongodb::Client;
+    use serde_json::{json, Map, Value};
+
+    use super::*;
+    use crate::auth::{api_key, AuthenticatedUser};
+
+    const HOUR: i64 = 3600;
+
+    fn claims(scope: &str, exp: Option<DateTime>) -> Claims {
+        let mut extra = Map::new();
+        extra.insert("scope".into(), Value::String(scope.into()));
+
+        Claims {
+            sub: "62f1b2c3d4e5f6a7b8c9d0e1".into(),
+            iss: None,
+            aud: None,
+            exp: exp.map(|exp| (exp.timestamp_millis() / 1000) as u64),
+            iat: None,
+            extra,
+        }
+    }
+
+    fn body(scopes: &[&str], expires_in: Option<i64>) -> body::ApiKeyBody {
+        body::ApiKeyBody {
+            name: "ci".into(),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+            expires_in,
+        }
+    }
+
+    fn now() -> DateTime {
+        //== whole seconds, as token expiries are
+        DateTime::from_millis(DateTime::now().timestamp_millis() / 1000 * 1000)
+    }
+
+    #[test]
+    fn issued_key_authenticates() {
+        let now = now();
+        let claims = claims("api_keys:write users:read", None);
+        let (api_key, key) = issue(&claims, &body(&["users:read"], Some(HOUR)), now).unwrap();
+
+        assert!(api_key::verify(&api_key, &key, now));
+        assert!(!api_key::verify(&api_key, "ak_nope", now));
+
+        let key_claims = Claims::from(&api_key);
+        assert_eq!(key_claims.sub, claims.sub);
+        assert_eq!(key_claims.scopes(), ["users:read"]);
+    }
+
+    #[test]
+    fn issued_key_stops_authenticating_when_expired() {
+        let now = now();
+        let claims = claims("api_keys:write", None);
+        let (api_key, key) = issue(&claims, &body(&[], Some(HOUR)), now).unwrap();
+
+        let expires = api_key.expires.unwrap();
+        assert_eq!(expires, expires_at(now, HOUR).unwrap());
+        assert!(!api_key::verify(&api_key, &key, expires));
+    }
+
+    #[test]
+    fn expiry_is_capped_at_the_caller() {
+        let now = now();
+        let caller_expires = expires_at(now, 600).unwrap();
+        let claims = claims("api_keys:write", Some(caller_expires));
+
+        let (api_key, _) = issue(&claims, &body(&[], Some(HOUR)), now).unwrap();
+        assert_eq!(api_key.expires, Some(caller_expires));
+
+        let (api_key, _) = issue(&claims, &body(&[], Some(60)), now).unwrap();
+        assert_eq!(api_key.expires, expires_at(now, 60));
+    }
+
+    #[test]
+    fn expiring_caller_must_set_a_lifetime() {
+        let claims = claims("api_keys:write", Some(expires_at(now(), 600).unwrap()));
+
+        let error = issue(&claims, &body(&[], None), now()).err().unwrap();
+        assert_eq!(error.code, StatusCode::BAD_REQUEST);
+        assert_eq!(error.detail, Some(json!({ "field": "expires_in" })));
+    }
+
+    #[test]
+    fn non_expiring_caller_may_issue_non_expiring_keys() {
+        let claims = claims("api_keys:write", None);
+        let (api_key, _) = issue(&claims, &body(&[], None), now()).unwrap();
+        assert_eq!(api_key.expires, None);
+    }
+
+    #[test]
+    fn ungranted_scopes_are_forbidden() {
+        let claims = claims("api_keys:write", None);
+        let error = issue(&claims, &body(&["users:write"], None), now())
+            .err()
+            .unwrap();
+        assert_eq!(error.code, StatusCode::FORBIDDEN);
+        assert_eq!(
+            error.detail,
+            Some(json!({ "missing": ["scope:users:write"] }))
+        );
+    }
+
+    #[actix_web::test]
+    async fn create_without_lifetime_is_rejected_for_expiring_callers() {
+        let db = Client::with_uri_str("mongodb://127.0.0.1:1")
+            .await
+            .unwrap()
+            .database("test");
+        let claims = claims("api_keys:write", Some(expires_at(now(), 600).unwrap()));
+
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(db))
+                .wrap_fn(move |req, srv| {
+                    req.extensions_mut()
+                        .insert(AuthenticatedUser(claims.clone()));
+                    srv.call(req)
+                })
+                .route("/api-keys", web::post().to(create_api_key)),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/api-keys")
+            .set_json(json!({ "name": "ci" }))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn expires_at_adds_lifetime() {
+        let now = DateTime::from_millis(1_000);
+        assert_eq!(expires_at(now, 60), Some(DateTime::from_millis(61_000)));
+    }
+
+    #[test]
+    fn expires_at_rejects_overflow() {
+        assert_eq!(expires_at(DateTime::now(), i64::MAX / 10), None);
+    }
+
+    #[test]
+    fn expires_in_is_bounded() {
+        let body = |expires_in| body::ApiKeyBody {
+            name: "ci".into(),
+            scopes: vec![],
+            expires_in: Some(expires_in),
+        };
+        assert!(body(60).validate().is_ok());
+        assert!(body(315_360_000).validate().is_ok());
+        assert!(body(315_360_001).validate().is_err());
+        assert!(body(i64::MAX).validate().is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use actix_web::{http::StatusCode, HttpResponse, Responder};
+
+use crate::{error::ErrorCode, metrics, RequestError, RequestResult};
+
+///
+/// Prometheus Metrics
+///
+pub async fn get_metrics() -> RequestResult<impl Responder> {
+    let (content_type, body) = metrics::encode().map_err(|e| {
+        RequestError::builder()
+            .code(StatusCode::INTERNAL_SERVER_ERROR)
+            .error(ErrorCode::InternalServerError)
+            .message("Failed to encode metrics")
+            .source(Some(e.to_string().into()))
+            .build()
+    })?;
+
+    Ok(HttpResponse::Ok().content_type(content_type).body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        test::{call_service, init_service, read_body, TestRequest},
+        web, App,
+    };
+
+    use super::*;
+    use crate::{utils::mongo::Observe, web::RequestSpan};
+
+    async fn get_widget(id: web::Path<u32>) -> HttpResponse {
+        async {}.observe("metrics_test_widgets", "find_one").await;
+
+        match id.into_inner() {
+            0 => HttpResponse::NotFound().finish(),
+            _ => HttpResponse::Ok().finish(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn requests_and_operations_are_rendered_by_route() {
+        let app = init_service(
+            App::new()
+                .wrap(RequestSpan)
+                .route("/metrics", web::get().to(get_metrics))
+                .route("/metrics-test/widgets/{id}", web::get().to(get_widget)),
+        )
+        .await;
+
+        for uri in [
+            "/metrics-test/widgets/1",
+            "/metrics-test/widgets/2",
+            "/metrics-test/widgets/0",
+        ] {
+            call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+        }
+
+        let labels = ["GET", "/metrics-test/widgets/{id}", "200"];
+        assert_eq!(metrics::HTTP_REQUESTS.with_label_values(&labels).get(), 2);
+        assert_eq!(
+            metrics::HTTP_REQUEST_DURATION
+                .with_label_values(&labels)
+                .get_sample_count(),
+            2
+        );
+        assert_eq!(
+            metrics::MONGO_OPERATION_DURATION
+                .with_label_values(&["metrics_test_widgets", "find_one"])
+                .get_sample_count(),
+            3
+        );
+
+        let resp = call_service(&app, TestRequest::get().uri("/metrics").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = read_body(resp).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains(
+            r#"http_requests_total{method="GET",route="/metrics-test/widgets/{id}",status="200"} 2"#
+        ));
+        assert!(body.contains(
+            r#"http_requests_total{method="GET",route="/metrics-test/widgets/{id}",status="404"} 1"#
+        ));
+        assert!(body.contains(
+            r#"http_request_duration_seconds_count{method="GET",route="/metrics-test/widgets/{id}",status="200"} 2"#
+        ));
+        assert!(body.contains(
+            r#"mongo_operation_duration_seconds_count{collection="metrics_test_widgets",operation="find_one"} 3"#
+        ));
+        assert!(!body.contains("/metrics-test/widgets/1"));
+    }
+}
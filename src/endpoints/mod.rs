@@ -1,2 +1,5 @@
 
+pub mod api_keys;
+pub mod health;
+pub mod metrics;
 pub mod users;
\ No newline at end of file
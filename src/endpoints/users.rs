@@ -1,17 +1,21 @@
-use actix_web::{http::StatusCode, web, Responder};
+use actix_web::{http::StatusCode, web, HttpResponse, Responder};
 use mongodb::{
-    bson::doc,
-    options::{FindOneAndUpdateOptions, ReturnDocument},
+    bson::{self, doc, Document},
+    options::{FindOneAndReplaceOptions, FindOneAndUpdateOptions, FindOneOptions, ReturnDocument},
     Database,
 };
 use serde::{Deserialize, Serialize};
+use validator::Validate;
 
 use crate::{
-    fields::{EmailOrObjectId, FromPath},
+    auth::{Require, Scopes, SelfOr},
+    error::ErrorCode,
+    fields::EmailOrObjectId,
     models::User,
-    schemas::{Page, PageBuilder},
-    web::Query,
-    MongoCollection, MongoFilter, RequestError, RequestResult,
+    schemas::{Page, PageBuilder, ProjectionParams},
+    utils::mongo::{is_duplicate_key_error, Observe},
+    web::{Json, Query, ValidPath},
+    MongoCollection, MongoFilter, MongoOptionalFilter, Projectable, RequestError, RequestResult,
 };
 
 ///
@@ -21,31 +25,122 @@ pub async fn get_users(
     query: Query<qparams::GetUsersParams>,
     db: web::Data<Database>,
 ) -> RequestResult<impl Responder> {
+    let filter = query.mongo_filter()?;
+    let sort = query.mongo_sort()?;
+    let projection = query.projection.projection(&User::projection_fields())?;
+
     //== create collection cursor
     let cursor = User::collection(&db)
-        .find(None, query.mongo_find_options()?)
+        .find(
+            query.page_params.mongo_filter(filter.clone(), &sort)?,
+            query.mongo_find_options(sort.clone(), projection.as_ref())?,
+        )
+        .observe(User::collection_name(), "find")
         .await?;
 
+    //== count all matching documents, only when asked while paging by cursor
+    let total = if query.page_params.counts() {
+        let total = User::collection::<User>(&db)
+            .count_documents(filter, query.mongo_count_options())
+            .observe(User::collection_name(), "count_documents")
+            .await?;
+        Some(total)
+    } else {
+        None
+    };
+
     //== build page of results and return
-    let page: Page<User> = PageBuilder::from(&query.page_params).build(cursor).await?;
-    Ok(web::Json(page))
+    let builder = PageBuilder::from(&query.page_params)
+        .total(total)
+        .sort(sort);
+
+    match projection {
+        Some(projection) => {
+            let page: Page<Document> = builder
+                .build(cursor)
+                .observe(User::collection_name(), "get_more")
+                .await?;
+            Ok(HttpResponse::Ok().json(page.map(|user| projection.apply(user))))
+        }
+        None => {
+            let page: Page<User> = builder
+                .build(cursor)
+                .observe(User::collection_name(), "get_more")
+                .await?;
+            Ok(HttpResponse::Ok().json(page))
+        }
+    }
 }
 
 ///
 /// Get Single User
 ///
 pub async fn get_user(
-    id: web::Path<String>,
+    id: ValidPath<EmailOrObjectId>,
+    query: Query<ProjectionParams>,
     db: web::Data<Database>,
 ) -> RequestResult<impl Responder> {
-    let id = EmailOrObjectId::from_path(":id", id.as_ref())?;
+    let projection = query.projection(&User::projection_fields())?;
 
     //== get user
-    let user = User::collection(&db)
-        .find_one(id.mongo_filter()?, None)
+    let user = User::collection::<Document>(&db)
+        .find_one(
+            id.mongo_filter()?,
+            FindOneOptions::builder()
+                .projection(projection.as_ref().map(|p| p.mongo_projection(None)))
+                .build(),
+        )
+        .observe(User::collection_name(), "find_one")
         .await?;
 
     //== unwrap and return user
+    let user = user.ok_or_else(errs::user_not_found)?;
+    match projection {
+        Some(projection) => Ok(HttpResponse::Ok().json(projection.apply(user))),
+        None => Ok(HttpResponse::Ok().json(bson::from_document::<User>(user)?)),
+    }
+}
+
+///
+/// Create Single User
+///
+pub async fn create_user(
+    _: Require<Scopes<perms::UsersWrite>>,
+    db: web::Data<Database>,
+    body: Json<body::UserBody>,
+) -> RequestResult<impl Responder> {
+    //== insert user
+    let user = body.into_inner().into_user();
+    User::collection::<User>(&db)
+        .insert_one(&user, None)
+        .observe(User::collection_name(), "insert_one")
+        .await
+        .map_err(errs::from_write_error)?;
+
+    Ok(HttpResponse::Created().json(user))
+}
+
+///
+/// Replace Single User
+///
+pub async fn replace_user(
+    _: Require<SelfOr<Scopes<perms::UsersWrite>>>,
+    id: ValidPath<EmailOrObjectId>,
+    db: web::Data<Database>,
+    body: Json<body::UserBody>,
+) -> RequestResult<impl Responder> {
+    let user = User::collection(&db)
+        .find_one_and_replace(
+            id.mongo_filter()?,
+            body.into_inner().into_user(),
+            FindOneAndReplaceOptions::builder()
+                .return_document(ReturnDocument::After)
+                .build(),
+        )
+        .observe(User::collection_name(), "find_one_and_replace")
+        .await
+        .map_err(errs::from_write_error)?;
+
     let user: User = user.ok_or_else(errs::user_not_found)?;
     Ok(web::Json(user))
 }
@@ -54,12 +149,11 @@ pub async fn get_user(
 /// Update Single User
 ///
 pub async fn update_user(
-    id: web::Path<String>,
+    _: Require<SelfOr<Scopes<perms::UsersWrite>>>,
+    id: ValidPath<EmailOrObjectId>,
     db: web::Data<Database>,
-    body: web::Json<body::UpdateUserBody>,
+    body: Json<body::UpdateUserBody>,
 ) -> RequestResult<impl Responder> {
-    let id = EmailOrObjectId::from_path(":id", &*id)?;
-
     let user = User::collection(&db)
         .find_one_and_update(
             id.mongo_filter()?,
@@ -68,57 +162,146 @@ pub async fn update_user(
                 .return_document(ReturnDocument::After)
                 .build(),
         )
+        .observe(User::collection_name(), "find_one_and_update")
         .await?;
 
     let user: User = user.ok_or_else(errs::user_not_found)?;
     Ok(web::Json(user))
 }
 
+///
+/// Delete Single User
+///
+pub async fn delete_user(
+    _: Require<Scopes<perms::UsersWrite>>,
+    id: ValidPath<EmailOrObjectId>,
+    db: web::Data<Database>,
+) -> RequestResult<impl Responder> {
+    let result = User::collection::<User>(&db)
+        .delete_one(id.mongo_filter()?, None)
+        .observe(User::collection_name(), "delete_one")
+        .await?;
+
+    if result.deleted_count == 0 {
+        return Err(errs::user_not_found());
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+mod perms {
+    crate::scopes!(UsersWrite => ["users:write"]);
+}
+
 mod errs {
     use super::*;
 
     pub fn user_not_found() -> RequestError {
         RequestError::builder()
             .code(StatusCode::NOT_FOUND)
+            .error(ErrorCode::ResourceNotFound)
             .message("User not found")
             .build()
     }
+
+    pub fn user_email_conflict() -> RequestError {
+        RequestError::builder()
+            .code(StatusCode::CONFLICT)
+            .error(ErrorCode::ResourceConflict)
+            .message("User with email already exists")
+            .build()
+    }
+
+    pub fn from_write_error(error: mongodb::error::Error) -> RequestError {
+        if is_duplicate_key_error(&error) {
+            user_email_conflict()
+        } else {
+            error.into()
+        }
+    }
 }
 
 mod qparams {
     use super::*;
-    use mongodb::options::FindOptions;
-    use validator::Validate;
+    use indexmap::IndexMap;
+    use mongodb::options::{CountOptions, FindOptions};
 
-    use crate::{fields::SortFields, schemas::PageParams, sortfields};
+    use crate::{
+        fields::{PageCursor, Projection, SortFields},
+        schemas::PageParams,
+        web::QueryValues,
+        Filterable, Sortable,
+    };
 
     #[derive(Serialize, Deserialize, Validate)]
     pub struct GetUsersParams {
         pub o: Option<String>,
 
         #[serde(flatten)]
+        #[validate]
         pub page_params: PageParams,
+
+        #[serde(flatten)]
+        pub projection: ProjectionParams,
+
+        //== in query order, the filter built from them depends on it
+        #[serde(flatten)]
+        pub filters: IndexMap<String, QueryValues>,
     }
 
     impl GetUsersParams {
-        pub fn mongo_find_options(&self) -> Result<Option<FindOptions>, RequestError> {
+        fn sort_fields() -> SortFields {
+            User::sort_fields().case_insensitive(true)
+        }
+
+        pub fn mongo_sort(&self) -> Result<Document, RequestError> {
             let sort = if let Some(ref _sort) = self.o {
-                let sort_fields = sortfields!["last_name", "first_name", "email"];
-                Some(sort_fields.sort_options(_sort.as_str())?)
+                Some(Self::sort_fields().sort_options(_sort.as_str())?)
             } else {
                 None
             };
 
+            Ok(PageCursor::keyset_sort(sort))
+        }
+
+        pub fn mongo_find_options(
+            &self,
+            sort: Document,
+            projection: Option<&Projection>,
+        ) -> Result<Option<FindOptions>, RequestError> {
             Ok(Some(
                 FindOptions::builder()
                     .limit(self.page_params.limit)
+                    .skip(self.page_params.skip())
+                    .projection(projection.map(|p| p.mongo_projection(Some(&sort))))
                     .sort(sort)
+                    .collation(Self::sort_fields().collation())
                     .build(),
             ))
         }
 
-        fn mongo_filter(&self) -> Result<Option<mongodb::bson::Document>, RequestError> {
-            Ok(None)
+        ///
+        /// Counts with the collation of `mongo_find_options` so `total`
+        /// matches the filtered items
+        ///
+        pub fn mongo_count_options(&self) -> Option<CountOptions> {
+            Some(
+                CountOptions::builder()
+                    .collation(Self::sort_fields().collation())
+                    .build(),
+            )
+        }
+    }
+
+    impl MongoOptionalFilter for GetUsersParams {
+        type Error = RequestError;
+
+        fn mongo_filter(&self) -> Result<Option<Document>, Self::Error> {
+            User::filter_fields().mongo_filter(
+                self.filters
+                    .iter()
+                    .flat_map(|(key, values)| values.iter().map(move |value| (key, value))),
+            )
         }
     }
 }
@@ -126,9 +309,35 @@ mod qparams {
 mod body {
     use super::*;
     use mongodb::options::UpdateModifications;
-    use validator::Validate;
 
-    use crate::{error::ErrorCode, validators};
+    use crate::validators;
+
+    #[derive(Serialize, Deserialize, Validate)]
+    #[serde(deny_unknown_fields)]
+    pub struct UserBody {
+        #[validate(custom = "validators::validate_alpha_numeric")]
+        first_name: String,
+
+        #[validate(custom = "validators::validate_alpha_numeric")]
+        last_name: String,
+
+        #[validate(email)]
+        email: String,
+
+        #[validate(custom = "validators::validate_iso_8601")]
+        last_login: String,
+    }
+
+    impl UserBody {
+        pub fn into_user(self) -> User {
+            User {
+                first_name: self.first_name,
+                last_name: self.last_name,
+                email: self.email,
+                last_login: self.last_login,
+            }
+        }
+    }
 
     #[derive(Serialize, Deserialize, Validate)]
     #[serde(deny_unknown_fields)]
@@ -170,3 +379,224 @@ mod body {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        dev::Service,
+        test::{call_service, init_service, read_body_json, TestRequest},
+        App, FromRequest, HttpMessage,
+    };
+    use mongodb::{
+        error::{ErrorKind, WriteError, WriteFailure},
+        Client,
+    };
+    use serde_json::{json, Map, Value};
+
+    use super::*;
+    use crate::auth::{AuthenticatedUser, Claims};
+
+    const USER_ID: &str = "62f1b2c3d4e5f6a7b8c9d0e1";
+
+    ///
+    /// Sends `req` to the user write routes as a caller holding `scope`
+    ///
+    async fn call_as(scope: &'static str, req: TestRequest) -> (StatusCode, Value) {
+        let client = Client::with_uri_str("mongodb://127.0.0.1:1").await.unwrap();
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(client.database("test")))
+                .wrap_fn(move |req, srv| {
+                    let mut extra = Map::new();
+                    extra.insert("scope".into(), scope.into());
+                    req.extensions_mut().insert(AuthenticatedUser(Claims {
+                        sub: "caller".into(),
+                        iss: None,
+                        aud: None,
+                        exp: None,
+                        iat: None,
+                        extra,
+                    }));
+                    srv.call(req)
+                })
+                .route("/users", web::post().to(create_user))
+                .route("/users/{id}", web::put().to(replace_user))
+                .route("/users/{id}", web::patch().to(update_user))
+                .route("/users/{id}", web::delete().to(delete_user)),
+        )
+        .await;
+
+        let resp = call_service(&app, req.to_request()).await;
+        let status = resp.status();
+        (status, read_body_json(resp).await)
+    }
+
+    fn user_body() -> Value {
+        json!({
+            "first_name": "Ada",
+            "last_name": "Lovelace",
+            "email": "ada@example.com",
+            "last_login": "2022-08-09T10:00:00Z",
+        })
+    }
+
+    fn write_error(code: i32) -> mongodb::error::Error {
+        let error: WriteError =
+            bson::from_document(doc! { "code": code, "errmsg": "write failed" }).unwrap();
+        ErrorKind::Write(WriteFailure::WriteError(error)).into()
+    }
+
+    ///
+    /// Calls `get_users`, the mongo client connects lazily so requests
+    /// rejected by the extractors never reach a server
+    ///
+    async fn get_users_status(uri: &str) -> (StatusCode, Value) {
+        let client = Client::with_uri_str("mongodb://127.0.0.1:1").await.unwrap();
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(client.database("test")))
+                .route("/users", web::get().to(get_users)),
+        )
+        .await;
+
+        let resp = call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+        let status = resp.status();
+        (status, read_body_json(resp).await)
+    }
+
+    async fn get_users_params(uri: &str) -> qparams::GetUsersParams {
+        let req = TestRequest::get().uri(uri).to_http_request();
+        Query::<qparams::GetUsersParams>::extract(&req)
+            .await
+            .ok()
+            .unwrap()
+            .0
+    }
+
+    #[actix_web::test]
+    async fn counts_with_offsets_or_when_asked() {
+        assert!(get_users_params("/users").await.page_params.counts());
+        assert!(get_users_params("/users?offset=100")
+            .await
+            .page_params
+            .counts());
+        assert!(!get_users_params("/users?cursor=abc")
+            .await
+            .page_params
+            .counts());
+
+        let params = get_users_params("/users?cursor=abc&count=true").await;
+        assert!(params.page_params.counts());
+        assert!(params.filters.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn invalid_count_is_rejected() {
+        let (status, body) = get_users_status("/users?count=maybe").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "INVALID_QUERY_PARAM");
+    }
+
+    #[actix_web::test]
+    async fn zero_limit_is_rejected() {
+        let (status, body) = get_users_status("/users?limit=0").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "VALIDATION_ERROR");
+        assert!(body["detail"]["page_params"]["limit"].is_array());
+    }
+
+    #[actix_web::test]
+    async fn negative_offset_is_rejected() {
+        let (status, body) = get_users_status("/users?offset=-5").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "VALIDATION_ERROR");
+        assert!(body["detail"]["page_params"]["offset"].is_array());
+    }
+
+    #[actix_web::test]
+    async fn create_rejects_unknown_fields() {
+        let mut body = user_body();
+        body["admin"] = json!(true);
+
+        let req = TestRequest::post().uri("/users").set_json(body);
+        let (status, body) = call_as("users:write", req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "INVALID_BODY");
+    }
+
+    #[actix_web::test]
+    async fn create_rejects_invalid_fields() {
+        let mut body = user_body();
+        body["email"] = json!("not an email");
+
+        let req = TestRequest::post().uri("/users").set_json(body);
+        let (status, body) = call_as("users:write", req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "VALIDATION_ERROR");
+        assert!(body["detail"]["email"].is_array());
+    }
+
+    #[actix_web::test]
+    async fn create_requires_write_scope() {
+        let req = TestRequest::post().uri("/users").set_json(user_body());
+        let (status, _) = call_as("users:read", req).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn replace_rejects_unknown_fields() {
+        let mut body = user_body();
+        body["_id"] = json!(USER_ID);
+
+        let req = TestRequest::put()
+            .uri(&format!("/users/{}", USER_ID))
+            .set_json(body);
+        let (status, body) = call_as("users:write", req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "INVALID_BODY");
+    }
+
+    #[actix_web::test]
+    async fn replace_rejects_invalid_fields() {
+        let mut body = user_body();
+        body["last_login"] = json!("yesterday");
+
+        let req = TestRequest::put()
+            .uri(&format!("/users/{}", USER_ID))
+            .set_json(body);
+        let (status, body) = call_as("users:write", req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "VALIDATION_ERROR");
+        assert!(body["detail"]["last_login"].is_array());
+    }
+
+    #[actix_web::test]
+    async fn empty_update_is_invalid_body() {
+        let req = TestRequest::patch()
+            .uri(&format!("/users/{}", USER_ID))
+            .set_json(json!({}));
+        let (status, body) = call_as("users:write", req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "INVALID_BODY");
+    }
+
+    #[actix_web::test]
+    async fn delete_requires_write_scope() {
+        let req = TestRequest::delete().uri(&format!("/users/{}", USER_ID));
+        let (status, _) = call_as("users:read", req).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn duplicate_key_is_conflict() {
+        let error = errs::from_write_error(write_error(11000));
+        assert_eq!(error.code, StatusCode::CONFLICT);
+        assert_eq!(error.error, "RESOURCE_CONFLICT");
+    }
+
+    #[test]
+    fn other_write_errors_are_internal() {
+        let error = errs::from_write_error(write_error(121));
+        assert_eq!(error.code, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
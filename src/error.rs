@@ -1,24 +1,137 @@
 use std::{borrow::Cow, error::Error, fmt};
 
-use actix_web::{http::StatusCode, HttpResponse, ResponseError};
-use lazy_static::__Deref;
-use log::{error, warn};
-use serde_json::{json, Map};
+use actix_web::{
+    http::{
+        header::{HeaderName, HeaderValue, CONTENT_TYPE},
+        StatusCode,
+    },
+    HttpResponse, ResponseError,
+};
+use serde_json::{json, Map, Value};
+use tracing::{error, warn};
 use validator::{ValidationError, ValidationErrors};
 
-#[derive(Debug)]
+pub use api_settings::ErrorFormat;
+
+use crate::{
+    metrics,
+    web::{RequestId, X_REQUEST_ID},
+};
+
+#[derive(Debug, Clone)]
 pub struct RequestError {
     pub code: StatusCode,
     pub error: String,
     pub message: String,
     pub detail: Option<serde_json::Value>,
     pub source: Option<serde_json::Value>,
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    pub request_id: Option<String>,
 }
 
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
 impl RequestError {
     pub fn builder() -> RequestErrorBuilder {
         RequestErrorBuilder::default()
     }
+
+    pub fn legacy_body(&self) -> Value {
+        json!({
+            "code": self.code.as_u16().to_string(),
+            "error": self.error,
+            "message": self.message,
+            "detail": self.detail,
+            "request_id": self.request_id
+        })
+    }
+
+    ///
+    /// RFC 7807 problem details. `type` is `type_base` followed by the
+    /// kebab-cased error code, `code` and `errors` are extension members.
+    ///
+    pub fn problem_body(&self, type_base: &str, instance: Option<&str>) -> Value {
+        let mut problem = Map::new();
+        problem.insert(
+            "type".into(),
+            format!("{}{}", type_base, ErrorCode::slug(&self.error)).into(),
+        );
+        problem.insert("title".into(), ErrorCode::title(&self.error).into());
+        problem.insert("status".into(), self.code.as_u16().into());
+        problem.insert("detail".into(), self.message.clone().into());
+        if let Some(instance) = instance {
+            problem.insert("instance".into(), instance.into());
+        }
+        problem.insert("code".into(), self.error.clone().into());
+        if let Some(ref detail) = self.detail {
+            problem.insert("errors".into(), detail.clone());
+        }
+        if let Some(ref request_id) = self.request_id {
+            problem.insert("request_id".into(), request_id.clone().into());
+        }
+
+        problem.into()
+    }
+
+    ///
+    /// Logs the error and counts it by error code
+    ///
+    pub fn report(&self) {
+        metrics::REQUEST_ERRORS
+            .with_label_values(&[&self.error])
+            .inc();
+
+        let status = self.code.as_u16();
+        let detail = self.detail.as_ref().map(|detail| detail.to_string());
+        let source = self.source.as_ref().map(|source| source.to_string());
+
+        if self.status_code().is_client_error() {
+            warn!(
+                status,
+                error = %self.error,
+                detail = detail.as_deref(),
+                source = source.as_deref(),
+                request_id = self.request_id.as_deref(),
+                "{}",
+                self.message
+            );
+        } else if self.status_code().is_server_error() {
+            error!(
+                status,
+                error = %self.error,
+                detail = detail.as_deref(),
+                source = source.as_deref(),
+                request_id = self.request_id.as_deref(),
+                "{}",
+                self.message
+            );
+        }
+    }
+
+    ///
+    /// Builds the response in `format` without logging it
+    ///
+    pub fn response(
+        &self,
+        format: ErrorFormat,
+        type_base: &str,
+        instance: Option<&str>,
+    ) -> HttpResponse {
+        let mut resp = HttpResponse::build(self.status_code());
+        for header in self.headers.iter() {
+            resp.append_header(header.clone());
+        }
+        if let Some(ref request_id) = self.request_id {
+            resp.insert_header((X_REQUEST_ID, request_id.as_str()));
+        }
+
+        match format {
+            ErrorFormat::Legacy => resp.json(self.legacy_body()),
+            ErrorFormat::Problem => resp
+                .insert_header((CONTENT_TYPE, PROBLEM_JSON))
+                .body(self.problem_body(type_base, instance).to_string()),
+        }
+    }
 }
 
 pub struct RequestErrorBuilder {
@@ -27,6 +140,7 @@ pub struct RequestErrorBuilder {
     message: String,
     detail: Option<serde_json::Value>,
     source: Option<serde_json::Value>,
+    headers: Vec<(HeaderName, HeaderValue)>,
 }
 
 impl Default for RequestErrorBuilder {
@@ -37,6 +151,7 @@ impl Default for RequestErrorBuilder {
             message: "".into(),
             detail: None,
             source: None,
+            headers: vec![],
         }
     }
 }
@@ -70,17 +185,36 @@ impl RequestErrorBuilder {
         self
     }
 
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
     pub fn build(self) -> RequestError {
+        //== fall back to the error code implied by the status
+        let error = if self.error.is_empty() {
+            ErrorCode::from_status(self.code).into()
+        } else {
+            self.error
+        };
+
         RequestError {
             code: self.code,
-            error: self.error,
+            error,
             message: self.message,
             detail: self.detail,
             source: self.source,
+            headers: self.headers,
+            request_id: current_request_id(),
         }
     }
 }
 
+/// Errors are tagged with the id of the request they are raised in
+fn current_request_id() -> Option<String> {
+    RequestId::current().map(|id| id.0)
+}
+
 impl Error for RequestError {}
 
 impl fmt::Display for RequestError {
@@ -90,10 +224,11 @@ impl fmt::Display for RequestError {
             "error": self.error,
             "message":self.message,
             "detail":self.detail,
-            "source": self.source
+            "source": self.source,
+            "request_id": self.request_id
         });
 
-        write!(f, "\n{}", serde_json::to_string_pretty(&json).unwrap())
+        write!(f, "{}", json)
     }
 }
 
@@ -103,19 +238,8 @@ impl ResponseError for RequestError {
     }
 
     fn error_response(&self) -> HttpResponse {
-        if self.status_code().is_client_error() {
-            warn!("{}", self.to_string());
-        } else if self.status_code().is_server_error() {
-            error!("{}", self.to_string());
-        }
-
-        let json = json!({
-            "error": self.error,
-            "message": self.message,
-            "detail": self.detail
-        });
-
-        HttpResponse::build(self.status_code()).json(json)
+        self.report();
+        self.response(ErrorFormat::Legacy, DEFAULT_PROBLEM_TYPE_BASE, None)
     }
 }
 
@@ -123,14 +247,28 @@ impl From<mongodb::error::Error> for RequestError {
     fn from(error: mongodb::error::Error) -> Self {
         Self {
             code: StatusCode::INTERNAL_SERVER_ERROR,
-            error: StatusCode::INTERNAL_SERVER_ERROR.to_string(),
+            error: ErrorCode::InternalServerError.into(),
+            message: StatusCode::INTERNAL_SERVER_ERROR.to_string(),
+            detail: None,
+            source: error
+                .source()
+                .map(|source| serde_json::Value::String(source.to_string())),
+            headers: vec![],
+            request_id: current_request_id(),
+        }
+    }
+}
+
+impl From<mongodb::bson::de::Error> for RequestError {
+    fn from(error: mongodb::bson::de::Error) -> Self {
+        Self {
+            code: StatusCode::INTERNAL_SERVER_ERROR,
+            error: ErrorCode::InternalServerError.into(),
             message: StatusCode::INTERNAL_SERVER_ERROR.to_string(),
             detail: None,
-            source: if let Some(source) = error.source() {
-                Some(serde_json::Value::String(source.to_string()))
-            } else {
-                None
-            },
+            source: Some(serde_json::Value::String(error.to_string())),
+            headers: vec![],
+            request_id: current_request_id(),
         }
     }
 }
@@ -149,6 +287,8 @@ impl From<ValidationError> for RequestError {
                 .into(),
             detail: Some(serde_json::value::to_value(error.params).unwrap()),
             source: None,
+            headers: vec![],
+            request_id: current_request_id(),
         }
     }
 }
@@ -167,36 +307,159 @@ impl From<ValidationErrors> for RequestError {
             message: "Validation Error".into(),
             detail: Some(errors.into()),
             source: None,
+            headers: vec![],
+            request_id: current_request_id(),
         }
     }
 }
 
+/// Prefix of problem `type` URIs unless configured otherwise
+pub const DEFAULT_PROBLEM_TYPE_BASE: &str = "/problems/";
+
 pub enum ErrorCode {
+    BadRequest,
     InvalidPathPart,
     InvalidQueryParam,
     ResourceNotFound,
+    ResourceConflict,
     ValidationError,
     InvalidBody,
+    Unauthorized,
+    Forbidden,
+    ServiceUnavailable,
     InternalServerError,
 }
 
-impl Into<String> for ErrorCode {
-    fn into(self) -> String {
-        self.to_string()
+impl ErrorCode {
+    ///
+    /// Error code implied by an HTTP status
+    ///
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => Self::Unauthorized,
+            StatusCode::FORBIDDEN => Self::Forbidden,
+            StatusCode::NOT_FOUND => Self::ResourceNotFound,
+            StatusCode::CONFLICT => Self::ResourceConflict,
+            StatusCode::SERVICE_UNAVAILABLE => Self::ServiceUnavailable,
+            s if s.is_server_error() => Self::InternalServerError,
+            _ => Self::BadRequest,
+        }
+    }
+
+    /// Stable `type` URI suffix of an error code, e.g. `resource-not-found`
+    pub fn slug(code: &str) -> String {
+        code.to_lowercase().replace('_', "-")
+    }
+
+    /// Problem title of an error code, e.g. `Resource not found`
+    pub fn title(code: &str) -> String {
+        let title = code.to_lowercase().replace('_', " ");
+        let mut chars = title.chars();
+
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => title,
+        }
+    }
+}
+
+impl From<ErrorCode> for String {
+    fn from(code: ErrorCode) -> Self {
+        code.to_string()
     }
 }
 
 impl fmt::Display for ErrorCode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let val = match *self {
+            Self::BadRequest => "BAD_REQUEST",
             Self::InvalidPathPart => "INVALID_PATH_PART",
             Self::ResourceNotFound => "RESOURCE_NOT_FOUND",
+            Self::ResourceConflict => "RESOURCE_CONFLICT",
             Self::InvalidQueryParam => "INVALID_QUERY_PARAM",
             Self::ValidationError => "VALIDATION_ERROR",
             Self::InvalidBody => "INVALID_BODY",
+            Self::Unauthorized => "UNAUTHORIZED",
+            Self::Forbidden => "FORBIDDEN",
+            Self::ServiceUnavailable => "SERVICE_UNAVAILABLE",
             Self::InternalServerError => "INTERNAL_SERVER_ERROR",
         };
 
         write!(f, "{}", val)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::to_bytes;
+
+    use super::*;
+
+    fn not_found() -> RequestError {
+        RequestError::builder()
+            .code(StatusCode::NOT_FOUND)
+            .message("User not found")
+            .detail(Some(json!({ "id": "42" })))
+            .build()
+    }
+
+    async fn body(resp: HttpResponse) -> (Option<String>, Value) {
+        let content_type = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .map(|value| value.to_str().unwrap().to_string());
+        let bytes = to_bytes(resp.into_body()).await.unwrap();
+        (content_type, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[test]
+    fn builder_defaults_error_to_status() {
+        assert_eq!(not_found().error, "RESOURCE_NOT_FOUND");
+    }
+
+    #[test]
+    fn problem_body_maps_error_fields() {
+        let problem = not_found().problem_body("https://example.com/problems/", Some("/users/42"));
+        assert_eq!(
+            problem,
+            json!({
+                "type": "https://example.com/problems/resource-not-found",
+                "title": "Resource not found",
+                "status": 404,
+                "detail": "User not found",
+                "instance": "/users/42",
+                "code": "RESOURCE_NOT_FOUND",
+                "errors": { "id": "42" },
+            })
+        );
+    }
+
+    #[actix_web::test]
+    async fn problem_response_is_problem_json() {
+        let resp = not_found().response(ErrorFormat::Problem, DEFAULT_PROBLEM_TYPE_BASE, None);
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let (content_type, body) = body(resp).await;
+        assert_eq!(content_type.as_deref(), Some(PROBLEM_JSON));
+        assert_eq!(body["type"], "/problems/resource-not-found");
+        assert!(body.get("instance").is_none());
+    }
+
+    #[actix_web::test]
+    async fn legacy_response_is_json() {
+        let resp = not_found().response(ErrorFormat::Legacy, DEFAULT_PROBLEM_TYPE_BASE, None);
+
+        let (content_type, body) = body(resp).await;
+        assert_eq!(content_type.as_deref(), Some("application/json"));
+        assert_eq!(
+            body,
+            json!({
+                "code": "404",
+                "error": "RESOURCE_NOT_FOUND",
+                "message": "User not found",
+                "detail": { "id": "42" },
+                "request_id": null,
+            })
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use actix_web::http::StatusCode;
 use mongodb::bson::{doc, oid::ObjectId, Document};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use validator;
 
 use super::FromPath;
@@ -18,30 +19,24 @@ pub enum EmailOrObjectId {
 }
 
 impl EmailOrObjectId {
-    fn validate(value: &String) -> Result<Self, String> {
+    fn validate(value: &str) -> Result<Self, String> {
         //== attempt ObjectId parse
-        if let Ok(value) = ObjectId::parse_str(&value) {
+        if let Ok(value) = ObjectId::parse_str(value) {
             return Ok(EmailOrObjectId::ObjectId(value));
         }
 
-        if !validator::validate_email(value.as_str()) {
+        if !validator::validate_email(value) {
             return Err("Invalid email or objectId value".into());
         }
 
         //== set as email and validate
-        return Ok(EmailOrObjectId::Email(value.clone()));
+        Ok(EmailOrObjectId::Email(value.to_string()))
     }
 }
 
-impl FromPath<&String> for EmailOrObjectId {
-    fn from_path(name: &'static str, value: &String) -> Result<Self, RequestError> {
-        Ok(EmailOrObjectId::validate(&value).map_err(|e| {
-            RequestError::builder()
-                .code(StatusCode::BAD_REQUEST)
-                .error(ErrorCode::InvalidPathPart)
-                .message(e)
-                .build()
-        })?)
+impl FromPath<&str> for EmailOrObjectId {
+    fn from_path(name: &str, value: &str) -> Result<Self, RequestError> {
+        EmailOrObjectId::validate(value).map_err(|e| invalid_path_part(name, e))
     }
 }
 
@@ -55,3 +50,18 @@ impl MongoFilter for EmailOrObjectId {
         }
     }
 }
+
+impl FromPath<&str> for ObjectId {
+    fn from_path(name: &str, value: &str) -> Result<Self, RequestError> {
+        ObjectId::parse_str(value).map_err(|_| invalid_path_part(name, "Invalid objectId value"))
+    }
+}
+
+fn invalid_path_part(name: &str, message: impl Into<String>) -> RequestError {
+    RequestError::builder()
+        .code(StatusCode::BAD_REQUEST)
+        .error(ErrorCode::InvalidPathPart)
+        .message(message)
+        .detail(Some(json!({ "segment": name })))
+        .build()
+}
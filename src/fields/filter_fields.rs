@@ -0,0 +1,332 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use mongodb::bson::{doc, Bson, Document};
+
+use crate::{error::ErrorCode, RequestError};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    In,
+    Nin,
+    Contains,
+}
+
+impl FilterOp {
+    pub const ALL: &'static [FilterOp] = &[
+        Self::Eq,
+        Self::Ne,
+        Self::Gt,
+        Self::Gte,
+        Self::Lt,
+        Self::Lte,
+        Self::In,
+        Self::Nin,
+        Self::Contains,
+    ];
+
+    fn mongo_op(&self) -> &'static str {
+        match *self {
+            Self::Eq => "$eq",
+            Self::Ne => "$ne",
+            Self::Gt => "$gt",
+            Self::Gte => "$gte",
+            Self::Lt => "$lt",
+            Self::Lte => "$lte",
+            Self::In => "$in",
+            Self::Nin => "$nin",
+            Self::Contains => "$regex",
+        }
+    }
+
+    fn mongo_value(&self, value: &str) -> Bson {
+        match *self {
+            Self::In | Self::Nin => value
+                .split(',')
+                .map(|v| Bson::String(v.to_string()))
+                .collect::<Vec<Bson>>()
+                .into(),
+            Self::Contains => Bson::String(regex::escape(value)),
+            _ => Bson::String(value.to_string()),
+        }
+    }
+}
+
+impl FromStr for FilterOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eq" => Ok(Self::Eq),
+            "ne" => Ok(Self::Ne),
+            "gt" => Ok(Self::Gt),
+            "gte" => Ok(Self::Gte),
+            "lt" => Ok(Self::Lt),
+            "lte" => Ok(Self::Lte),
+            "in" => Ok(Self::In),
+            "nin" => Ok(Self::Nin),
+            "contains" => Ok(Self::Contains),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for FilterOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let val = match *self {
+            Self::Eq => "eq",
+            Self::Ne => "ne",
+            Self::Gt => "gt",
+            Self::Gte => "gte",
+            Self::Lt => "lt",
+            Self::Lte => "lte",
+            Self::In => "in",
+            Self::Nin => "nin",
+            Self::Contains => "contains",
+        };
+
+        write!(f, "{}", val)
+    }
+}
+
+pub struct FilterField {
+    pub name: String,
+    pub ops: Vec<FilterOp>,
+}
+
+impl FilterField {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ops: FilterOp::ALL.to_vec(),
+        }
+    }
+
+    pub fn ops(mut self, ops: &[FilterOp]) -> Self {
+        self.ops = ops.to_vec();
+        self
+    }
+}
+
+impl From<&str> for FilterField {
+    fn from(s: &str) -> Self {
+        Self::new(s)
+    }
+}
+
+impl From<String> for FilterField {
+    fn from(s: String) -> Self {
+        Self::new(s)
+    }
+}
+
+pub struct FilterFields {
+    fields: HashMap<String, FilterField>,
+}
+
+impl FilterFields {
+    pub fn new() -> Self {
+        Self {
+            fields: HashMap::new(),
+        }
+    }
+
+    pub fn from(fields: impl IntoIterator<Item = FilterField>) -> Self {
+        Self {
+            fields: fields
+                .into_iter()
+                .map(|field| (field.name.clone(), field))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, key: impl AsRef<str>) -> Option<&FilterField> {
+        self.fields.get(key.as_ref())
+    }
+
+    ///
+    /// Translates `field[__op]=value` query params into a mongo filter.
+    /// A bare field name is an equality match. Repeated equality params
+    /// match any of their values, merged with `in` values of the field
+    /// in either order.
+    ///
+    pub fn mongo_filter<K, V>(
+        &self,
+        params: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<Option<Document>, RequestError>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut filter = doc! {};
+
+        for (key, value) in params {
+            let key = key.as_ref();
+            let (name, op) = self.parse_filter_key(key)?;
+
+            if !filter.contains_key(name) {
+                filter.insert(name, doc! {});
+            }
+
+            let field = filter
+                .get_document_mut(name)
+                .expect("filter field is always a document");
+            let mut value = op.mongo_value(value.as_ref());
+
+            //== an `in` list also matches a previous equality value
+            if let (FilterOp::In, Bson::Array(values)) = (op, &mut value) {
+                if let Some(eq) = field.remove("$eq") {
+                    values.insert(0, eq);
+                }
+            }
+
+            if op == FilterOp::Eq && (field.contains_key("$eq") || field.contains_key("$in")) {
+                //== repeated equality matches any of the values
+                let mut values = match field.remove("$in") {
+                    Some(Bson::Array(values)) => values,
+                    _ => vec![],
+                };
+                values.extend(field.remove("$eq"));
+                values.push(value);
+                field.insert("$in", values);
+            } else if !field.contains_key(op.mongo_op()) {
+                field.insert(op.mongo_op(), value);
+            } else if let (Ok(values), Bson::Array(value)) =
+                (field.get_array_mut(op.mongo_op()), value)
+            {
+                //== repeated `in`/`nin` lists are merged
+                values.extend(value);
+            } else {
+                return Err(invalid_filter(
+                    key,
+                    format!("Filter operator '{}' repeated on field: {}", op, name),
+                ));
+            }
+
+            if op == FilterOp::Contains {
+                filter
+                    .get_document_mut(name)
+                    .expect("filter field is always a document")
+                    .insert("$options", "i");
+            }
+        }
+
+        Ok(if filter.is_empty() {
+            None
+        } else {
+            Some(filter)
+        })
+    }
+
+    fn parse_filter_key<'a>(&self, key: &'a str) -> Result<(&'a str, FilterOp), RequestError> {
+        let (name, op) = match key.rsplit_once("__") {
+            Some((name, op)) => (
+                name,
+                op.parse::<FilterOp>()
+                    .map_err(|_| invalid_filter(key, format!("Invalid filter operator: {}", op)))?,
+            ),
+            None => (key, FilterOp::Eq),
+        };
+
+        let field = self
+            .get(name)
+            .ok_or_else(|| invalid_filter(key, format!("Invalid filter field: {}", name)))?;
+
+        if !field.ops.contains(&op) {
+            return Err(invalid_filter(
+                key,
+                format!("Filter operator '{}' not allowed on field: {}", op, name),
+            ));
+        }
+
+        Ok((name, op))
+    }
+}
+
+impl Default for FilterFields {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn invalid_filter(key: &str, message: String) -> RequestError {
+    RequestError::builder()
+        .error(ErrorCode::InvalidQueryParam)
+        .message(message)
+        .detail(Some(key.into()))
+        .build()
+}
+
+#[macro_export]
+macro_rules! filterfields {
+    () => {
+        $crate::fields::FilterFields::new()
+    };
+    ($($x : expr), + $(,) ?) => {
+        $crate::fields::FilterFields::from([$($x.into()), +])
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter_fields() -> FilterFields {
+        filterfields![
+            "email",
+            FilterField::new("age").ops(&[FilterOp::Gt, FilterOp::Lt]),
+        ]
+    }
+
+    #[test]
+    fn empty_macro_has_no_fields() {
+        assert!(filterfields!().get("email").is_none());
+        assert!(FilterFields::default().get("email").is_none());
+    }
+
+    #[test]
+    fn repeated_equality_matches_any() {
+        let filter = filter_fields()
+            .mongo_filter([("email", "a"), ("email", "b"), ("email", "c")])
+            .unwrap();
+        assert_eq!(filter, Some(doc! { "email": { "$in": ["a", "b", "c"] } }));
+    }
+
+    #[test]
+    fn repeated_in_lists_are_merged() {
+        let filter = filter_fields()
+            .mongo_filter([("email__in", "a,b"), ("email__in", "c")])
+            .unwrap();
+        assert_eq!(filter, Some(doc! { "email": { "$in": ["a", "b", "c"] } }));
+    }
+
+    #[test]
+    fn equality_and_in_match_any_in_either_order() {
+        let eq_first = filter_fields()
+            .mongo_filter([("email", "a"), ("email__in", "b,c")])
+            .unwrap();
+        assert_eq!(eq_first, Some(doc! { "email": { "$in": ["a", "b", "c"] } }));
+
+        let in_first = filter_fields()
+            .mongo_filter([("email__in", "b,c"), ("email", "a")])
+            .unwrap();
+        assert_eq!(in_first, Some(doc! { "email": { "$in": ["b", "c", "a"] } }));
+    }
+
+    #[test]
+    fn repeated_comparison_is_rejected() {
+        assert!(filter_fields()
+            .mongo_filter([("age__gt", "1"), ("age__gt", "2")])
+            .is_err());
+    }
+
+    #[test]
+    fn disallowed_operator_is_rejected() {
+        assert!(filter_fields().mongo_filter([("age", "1")]).is_err());
+    }
+}
@@ -1,14 +1,23 @@
 use crate::RequestError;
 
 mod common;
+mod filter_fields;
+mod page_cursor;
+mod projection_fields;
 mod sort_fields;
 
 pub use common::EmailOrObjectId;
+pub use filter_fields::{FilterField, FilterFields, FilterOp};
+pub use page_cursor::PageCursor;
+pub use projection_fields::{Projection, ProjectionFields};
 pub use sort_fields::{SortField, SortFields};
 
+///
+/// Parses a single path segment, `name` is the segment's name in the route
+///
 pub trait FromPath<T>: Sized
 where
     T: Sized,
 {
-    fn from_path(name: &'static str, value: T) -> Result<Self, RequestError>;
+    fn from_path(name: &str, value: T) -> Result<Self, RequestError>;
 }
@@ -0,0 +1,164 @@
+use actix_web::http::StatusCode;
+use mongodb::bson::{doc, Bson, Document};
+
+use crate::{error::ErrorCode, RequestError};
+
+///
+/// PageCursor
+///
+/// Opaque keyset pagination token. Holds the sort key values (and `_id`)
+/// of the last document on a page, encoded as url safe base64 bson.
+///
+pub struct PageCursor {
+    values: Document,
+}
+
+impl PageCursor {
+    ///
+    /// Appends `_id` as a final tiebreaker so every ordering is total
+    ///
+    pub fn keyset_sort(sort: Option<Document>) -> Document {
+        let mut sort = sort.unwrap_or_default();
+
+        if !sort.contains_key("_id") {
+            sort.insert("_id", 1_i64);
+        }
+
+        sort
+    }
+
+    pub fn from_document(document: &Document, sort: &Document) -> Option<Self> {
+        if !document.contains_key("_id") {
+            return None;
+        }
+
+        let mut values = doc! {};
+        for key in sort.keys() {
+            let value = document.get(key).cloned().unwrap_or(Bson::Null);
+            values.insert(key.clone(), value);
+        }
+
+        Some(Self { values })
+    }
+
+    pub fn decode(token: &str) -> Result<Self, RequestError> {
+        base64::decode_config(token, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|bytes| Document::from_reader(bytes.as_slice()).ok())
+            .map(|values| Self { values })
+            .ok_or_else(invalid_cursor)
+    }
+
+    pub fn encode(&self) -> String {
+        let mut bytes = vec![];
+        self.values
+            .to_writer(&mut bytes)
+            .expect("page cursor values are always serializable");
+
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    ///
+    /// Builds a filter matching documents ordered strictly after this cursor.
+    /// The sort must be the same keyset sort the cursor was created from.
+    ///
+    pub fn mongo_filter(&self, sort: &Document) -> Result<Document, RequestError> {
+        if self.values.len() != sort.len() {
+            return Err(invalid_cursor());
+        }
+
+        let mut clauses = vec![];
+        let mut equal = doc! {};
+
+        for (key, direction) in sort {
+            let value = self.values.get(key).ok_or_else(invalid_cursor)?;
+            let op = if sort_direction(direction) < 0 {
+                "$lt"
+            } else {
+                "$gt"
+            };
+
+            let mut clause = equal.clone();
+            clause.insert(key.clone(), doc! { op: value.clone() });
+            clauses.push(Bson::Document(clause));
+
+            //== `$eq` keeps decoded documents from being read as operators
+            equal.insert(key.clone(), doc! { "$eq": value.clone() });
+        }
+
+        Ok(doc! { "$or": clauses })
+    }
+}
+
+fn sort_direction(value: &Bson) -> i64 {
+    match *value {
+        Bson::Int32(v) => v as i64,
+        Bson::Int64(v) => v,
+        Bson::Double(v) => v as i64,
+        _ => 1,
+    }
+}
+
+fn invalid_cursor() -> RequestError {
+    RequestError::builder()
+        .code(StatusCode::BAD_REQUEST)
+        .error(ErrorCode::InvalidQueryParam)
+        .message("Invalid page cursor")
+        .detail(Some("cursor".into()))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort() -> Document {
+        PageCursor::keyset_sort(Some(doc! { "last_name": 1_i64 }))
+    }
+
+    fn cursor(values: Document) -> PageCursor {
+        PageCursor::decode(&PageCursor { values }.encode()).unwrap()
+    }
+
+    #[test]
+    fn filter_orders_after_cursor() {
+        let filter = cursor(doc! { "last_name": "b", "_id": 7 })
+            .mongo_filter(&sort())
+            .unwrap();
+
+        assert_eq!(
+            filter,
+            doc! { "$or": [
+                { "last_name": { "$gt": "b" } },
+                { "last_name": { "$eq": "b" }, "_id": { "$gt": 7 } },
+            ] }
+        );
+    }
+
+    #[test]
+    fn tampered_values_are_not_operators() {
+        let filter = cursor(doc! { "last_name": { "$ne": null }, "_id": 7 })
+            .mongo_filter(&sort())
+            .unwrap();
+
+        let clauses = filter.get_array("$or").unwrap();
+        assert_eq!(
+            clauses[1],
+            Bson::Document(doc! {
+                "last_name": { "$eq": { "$ne": null } },
+                "_id": { "$gt": 7 },
+            })
+        );
+    }
+
+    #[test]
+    fn cursor_for_another_sort_is_rejected() {
+        let cursor = cursor(doc! { "_id": 7 });
+        assert!(cursor.mongo_filter(&sort()).is_err());
+    }
+
+    #[test]
+    fn malformed_token_is_rejected() {
+        assert!(PageCursor::decode("not a cursor").is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use mongodb::bson::{doc, Document};
+use serde_json::json;
+
+use crate::{error::ErrorCode, RequestError};
+
+///
+/// ProjectionFields
+///
+/// Whitelist of the fields clients may select with `fields=` or drop
+/// with `exclude=`, in the order they are serialized.
+///
+pub struct ProjectionFields {
+    fields: Vec<String>,
+}
+
+impl ProjectionFields {
+    pub fn new() -> Self {
+        Self { fields: vec![] }
+    }
+
+    pub fn from(fields: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            fields: fields.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn contains(&self, field: impl AsRef<str>) -> bool {
+        self.fields.iter().any(|f| f == field.as_ref())
+    }
+
+    ///
+    /// Resolves `fields` (kept) or `exclude` (dropped) comma separated
+    /// lists into a projection, `None` when neither is given
+    ///
+    pub fn projection<F, E>(
+        &self,
+        fields: Option<F>,
+        exclude: Option<E>,
+    ) -> Result<Option<Projection>, RequestError>
+    where
+        F: IntoIterator,
+        F::Item: AsRef<str>,
+        E: IntoIterator,
+        E::Item: AsRef<str>,
+    {
+        let selected: Vec<String> = match (fields, exclude) {
+            (None, None) => return Ok(None),
+            (Some(_), Some(_)) => {
+                return Err(invalid_projection(
+                    "exclude",
+                    "Query params 'fields' and 'exclude' cannot be combined".into(),
+                ))
+            }
+            (Some(fields), None) => {
+                let fields = self.parse("fields", fields)?;
+                self.fields
+                    .iter()
+                    .filter(|field| fields.contains(field))
+                    .cloned()
+                    .collect()
+            }
+            (None, Some(exclude)) => {
+                let exclude = self.parse("exclude", exclude)?;
+                self.fields
+                    .iter()
+                    .filter(|field| !exclude.contains(field))
+                    .cloned()
+                    .collect()
+            }
+        };
+
+        if selected.is_empty() {
+            return Err(invalid_projection(
+                "fields",
+                "Projection selects no fields".into(),
+            ));
+        }
+
+        Ok(Some(Projection { fields: selected }))
+    }
+
+    fn parse<T>(&self, param: &str, values: T) -> Result<Vec<String>, RequestError>
+    where
+        T: IntoIterator,
+        T::Item: AsRef<str>,
+    {
+        let mut fields = vec![];
+
+        for value in values {
+            for field in value.as_ref().split(',').map(str::trim) {
+                if field.is_empty() {
+                    continue;
+                }
+
+                if !self.contains(field) {
+                    return Err(invalid_projection(
+                        param,
+                        format!("Invalid projection field: {}", field),
+                    ));
+                }
+
+                fields.push(field.to_string());
+            }
+        }
+
+        Ok(fields)
+    }
+}
+
+impl Default for ProjectionFields {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// Projection
+///
+/// Fields selected by a client, in whitelist order
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Projection {
+    fields: Vec<String>,
+}
+
+impl Projection {
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    ///
+    /// Mongo projection of the selected fields, plus the keys of `sort`
+    /// which keyset cursors are built from
+    ///
+    pub fn mongo_projection(&self, sort: Option<&Document>) -> Document {
+        let mut projection = doc! {};
+        let sort_keys = sort.into_iter().flat_map(|sort| sort.keys());
+
+        for field in self.fields.iter().chain(sort_keys) {
+            projection.insert(field.as_str(), 1_i32);
+        }
+
+        projection
+    }
+
+    ///
+    /// Keeps only the selected fields of `document`
+    ///
+    pub fn apply(&self, mut document: Document) -> Document {
+        let mut projected = doc! {};
+
+        for field in self.fields.iter() {
+            if let Some(value) = document.remove(field) {
+                projected.insert(field.as_str(), value);
+            }
+        }
+
+        projected
+    }
+}
+
+fn invalid_projection(param: &str, message: String) -> RequestError {
+    RequestError::builder()
+        .error(ErrorCode::InvalidQueryParam)
+        .message(message)
+        .detail(Some(json!({ "param": param })))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn projection_fields() -> ProjectionFields {
+        ProjectionFields::from(["first_name", "last_name", "email", "last_login"])
+    }
+
+    fn projection(
+        fields: Option<&str>,
+        exclude: Option<&str>,
+    ) -> Result<Option<Projection>, RequestError> {
+        projection_fields().projection(fields.map(|f| [f]), exclude.map(|e| [e]))
+    }
+
+    #[test]
+    fn default_has_no_fields() {
+        assert!(!ProjectionFields::default().contains("email"));
+    }
+
+    #[test]
+    fn no_params_is_no_projection() {
+        assert_eq!(projection(None, None).unwrap(), None);
+    }
+
+    #[test]
+    fn fields_keep_whitelist_order() {
+        let projection = projection(Some("email,first_name"), None).unwrap().unwrap();
+        assert_eq!(projection.fields(), ["first_name", "email"]);
+    }
+
+    #[test]
+    fn exclude_drops_fields() {
+        let projection = projection(None, Some("last_login")).unwrap().unwrap();
+        assert_eq!(projection.fields(), ["first_name", "last_name", "email"]);
+    }
+
+    #[test]
+    fn unknown_field_is_rejected() {
+        let error = projection(Some("password"), None).err().unwrap();
+        assert_eq!(error.error, "INVALID_QUERY_PARAM");
+        assert_eq!(error.detail, Some(json!({ "param": "fields" })));
+    }
+
+    #[test]
+    fn fields_and_exclude_are_exclusive() {
+        assert!(projection(Some("email"), Some("last_login")).is_err());
+    }
+
+    #[test]
+    fn empty_selection_is_rejected() {
+        assert!(projection(Some(","), None).is_err());
+    }
+
+    #[test]
+    fn mongo_projection_keeps_sort_keys() {
+        let projection = projection(Some("email"), None).unwrap().unwrap();
+        let sort = doc! { "last_name": 1_i64, "_id": 1_i64 };
+        assert_eq!(
+            projection.mongo_projection(Some(&sort)),
+            doc! { "email": 1, "last_name": 1, "_id": 1 }
+        );
+    }
+
+    #[test]
+    fn apply_keeps_selected_fields() {
+        let projection = projection(Some("email"), None).unwrap().unwrap();
+        let document = doc! { "_id": 1, "email": "a@b.c", "last_name": "b" };
+        assert_eq!(projection.apply(document), doc! { "email": "a@b.c" });
+    }
+}
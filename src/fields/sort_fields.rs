@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
-use mongodb::bson::{doc, Document};
+use mongodb::{
+    bson::{doc, Document},
+    options::{Collation, CollationStrength},
+};
 
 use crate::{error::ErrorCode, RequestError};
 
@@ -9,6 +12,13 @@ pub struct SortField {
     pub alias: Option<String>,
 }
 
+impl SortField {
+    /// Name clients use to sort by this field
+    pub fn external_name(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+}
+
 impl From<&str> for SortField {
     fn from(s: &str) -> Self {
         s.to_string().into()
@@ -47,38 +57,46 @@ struct SortValue<'a> {
 pub struct SortFields {
     fields: Vec<SortField>,
     lookup: HashMap<String, usize>,
+    max_keys: usize,
+    case_insensitive: bool,
 }
 
 impl SortFields {
+    pub const DEFAULT_MAX_KEYS: usize = 5;
+
     pub fn new() -> Self {
         Self {
             fields: vec![],
             lookup: HashMap::new(),
+            max_keys: Self::DEFAULT_MAX_KEYS,
+            case_insensitive: false,
         }
     }
 
     pub fn from(fields: impl IntoIterator<Item = SortField>) -> Self {
-        //let mut s = Self::new();
-
-        let mut _fields = vec![];
-        let mut _lookup = HashMap::new();
+        let mut sort_fields = Self::new();
 
         for field in fields {
-            let index = _fields.len();
-            _fields.push(field);
+            //== aliased fields are only addressable by their alias
+            sort_fields
+                .lookup
+                .insert(field.external_name().to_string(), sort_fields.fields.len());
+            sort_fields.fields.push(field);
+        }
 
-            let sort_field = _fields.last().unwrap();
-            _lookup.insert(sort_field.name.clone(), index);
+        sort_fields
+    }
 
-            if let Some(ref alias) = sort_field.alias {
-                _lookup.insert(alias.clone(), index);
-            }
-        }
+    /// Maximum number of keys accepted in a single sort
+    pub fn max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = max_keys;
+        self
+    }
 
-        Self {
-            fields: _fields,
-            lookup: _lookup,
-        }
+    /// Compare strings case-insensitively when sorting
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
     }
 
     pub fn get(&self, key: impl AsRef<str>) -> Option<&SortField> {
@@ -89,32 +107,90 @@ impl SortFields {
         }
     }
 
+    ///
+    /// Collation required by the sort, if any
+    ///
+    pub fn collation(&self) -> Option<Collation> {
+        if self.case_insensitive {
+            Some(
+                Collation::builder()
+                    .locale("en")
+                    .strength(CollationStrength::Secondary)
+                    .build(),
+            )
+        } else {
+            None
+        }
+    }
+
+    ///
+    /// Parses a sort expression such as `last_name,-email` or
+    /// `last_name:asc,email:desc` into a mongo sort document.
+    /// Keys may be separated by `,`, `+` or a space.
+    ///
     pub fn sort_options(&self, value: &str) -> Result<Document, RequestError> {
         let mut sort = doc! {};
 
-        for v in value.split('+') {
+        let values = value
+            .split([',', '+', ' '])
+            .filter(|v| !v.is_empty())
+            .collect::<Vec<&str>>();
+
+        if values.len() > self.max_keys {
+            return Err(invalid_sort(format!(
+                "Too many sort fields: at most {} allowed",
+                self.max_keys
+            )));
+        }
+
+        for v in values {
             let sort_value = self.parse_sort_field(v)?;
 
-            let direction: i64 = sort_value.direction.into();
-            sort.insert(sort_value.field.name.clone(), direction);
+            if sort.contains_key(&sort_value.field.name) {
+                return Err(invalid_sort(format!(
+                    "Duplicate sort field value: {}",
+                    sort_value.field.external_name()
+                )));
+            }
+
+            sort.insert(sort_value.field.name.clone(), sort_value.direction);
         }
 
         Ok(sort)
     }
 
-    fn parse_sort_field(&self, value: &str) -> Result<SortValue, RequestError> {
-        let (dir, name) = if value.starts_with('-') {
-            (0_i64, &value[1..])
-        } else {
-            (1_i64, value)
+    fn parse_sort_field<'a>(&'a self, value: &str) -> Result<SortValue<'a>, RequestError> {
+        let (prefix_dir, value) = match value.strip_prefix('-') {
+            Some(value) => (Some(-1_i64), value),
+            None => (None, value),
+        };
+
+        let (suffix_dir, name) = match value.rsplit_once(':') {
+            Some((name, "asc")) => (Some(1_i64), name),
+            Some((name, "desc")) => (Some(-1_i64), name),
+            Some((_, dir)) => {
+                return Err(invalid_sort(format!(
+                    "Invalid sort direction value: {}",
+                    dir
+                )))
+            }
+            None => (None, value),
+        };
+
+        let dir = match (prefix_dir, suffix_dir) {
+            (Some(_), Some(_)) => {
+                return Err(invalid_sort(format!(
+                    "Conflicting sort direction for field: {}",
+                    name
+                )))
+            }
+            (Some(dir), None) | (None, Some(dir)) => dir,
+            (None, None) => 1_i64,
         };
 
-        let sort_field = self.get(name).ok_or_else(|| {
-            RequestError::builder()
-                .error(ErrorCode::InvalidQueryParam)
-                .message(format!("Invalid sort field value: {}", name))
-                .build()
-        })?;
+        let sort_field = self
+            .get(name)
+            .ok_or_else(|| invalid_sort(format!("Invalid sort field value: {}", name)))?;
 
         Ok(SortValue {
             field: sort_field,
@@ -123,12 +199,113 @@ impl SortFields {
     }
 }
 
+impl Default for SortFields {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn invalid_sort(message: String) -> RequestError {
+    RequestError::builder()
+        .error(ErrorCode::InvalidQueryParam)
+        .message(message)
+        .build()
+}
+
 #[macro_export]
 macro_rules! sortfields {
     () => {
-        SortFields::new(&[])
+        $crate::fields::SortFields::new()
     };
     ($($x : expr), + $(,) ?) => {
-        crate::fields::SortFields::from([$($x.into()), +])
+        $crate::fields::SortFields::from([$($x.into()), +])
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort_fields() -> SortFields {
+        sortfields!["first_name", "email", ("last_name", "ln")]
+    }
+
+    #[test]
+    fn empty_macro_has_no_fields() {
+        assert!(sortfields!().get("email").is_none());
+        assert!(SortFields::default().get("email").is_none());
+    }
+
+    #[test]
+    fn descending_prefix_is_negative_one() {
+        let sort = sort_fields().sort_options("-email").unwrap();
+        assert_eq!(sort, doc! { "email": -1_i64 });
+    }
+
+    #[test]
+    fn ascending_by_default() {
+        let sort = sort_fields().sort_options("email").unwrap();
+        assert_eq!(sort, doc! { "email": 1_i64 });
+    }
+
+    #[test]
+    fn direction_suffix() {
+        let sort = sort_fields()
+            .sort_options("email:desc,first_name:asc")
+            .unwrap();
+        assert_eq!(sort, doc! { "email": -1_i64, "first_name": 1_i64 });
+    }
+
+    #[test]
+    fn invalid_direction_suffix() {
+        assert!(sort_fields().sort_options("email:down").is_err());
+    }
+
+    #[test]
+    fn conflicting_prefix_and_suffix() {
+        assert!(sort_fields().sort_options("-email:asc").is_err());
+    }
+
+    #[test]
+    fn preserves_key_order() {
+        let sort = sort_fields().sort_options("email+-first_name").unwrap();
+        let keys: Vec<&String> = sort.keys().collect();
+        assert_eq!(keys, ["email", "first_name"]);
+    }
+
+    #[test]
+    fn alias_is_external_name() {
+        let sort = sort_fields().sort_options("-ln").unwrap();
+        assert_eq!(sort, doc! { "last_name": -1_i64 });
+        assert!(sort_fields().sort_options("last_name").is_err());
+    }
+
+    #[test]
+    fn unknown_field() {
+        assert!(sort_fields().sort_options("password").is_err());
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        assert!(sort_fields().sort_options("email,-email").is_err());
+    }
+
+    #[test]
+    fn max_keys() {
+        let sort_fields = sort_fields().max_keys(2);
+        assert!(sort_fields.sort_options("email,first_name").is_ok());
+        assert!(sort_fields.sort_options("email,first_name,ln").is_err());
+    }
+
+    #[test]
+    fn case_insensitive_collation() {
+        assert!(sort_fields().collation().is_none());
+
+        let collation = sort_fields().case_insensitive(true).collation().unwrap();
+        assert_eq!(collation.locale, "en");
+        assert!(matches!(
+            collation.strength,
+            Some(CollationStrength::Secondary)
+        ));
+    }
+}
@@ -1,23 +1,67 @@
-use actix_web;
+//== `RequestError` is returned by value everywhere, its fields are public
+#![allow(clippy::result_large_err)]
 
+extern crate self as api;
+
+pub mod auth;
 pub mod endpoints;
 pub mod fields;
+pub mod metrics;
 pub mod models;
 pub mod schemas;
+pub mod telemetry;
 pub mod utils;
 pub mod validators;
 pub mod web;
 
 mod error;
-pub use error::{ErrorCode, RequestError, RequestErrorBuilder};
+pub use error::{ErrorCode, ErrorFormat, RequestError, RequestErrorBuilder};
+
+pub use api_derive::{Filterable, MongoCollection, Projectable, Sortable};
+pub use api_settings::Settings;
 
+use fields::{FilterField, FilterFields, FilterOp, ProjectionFields, SortField, SortFields};
 use mongodb::{bson::Document, Collection, Database};
 
 pub type RequestResult<T> = std::result::Result<T, RequestError>;
 
 pub trait MongoCollection {
     fn collection_name() -> &'static str;
-    fn collection<T: Sized>(db: &actix_web::web::Data<Database>) -> Collection<T>;
+
+    fn collection<T: Sized>(db: &actix_web::web::Data<Database>) -> Collection<T> {
+        db.collection(Self::collection_name())
+    }
+}
+
+pub trait Sortable {
+    const SORT_FIELDS: &'static [(&'static str, Option<&'static str>)];
+
+    fn sort_fields() -> SortFields {
+        SortFields::from(Self::SORT_FIELDS.iter().map(|&(name, alias)| SortField {
+            name: name.into(),
+            alias: alias.map(String::from),
+        }))
+    }
+}
+
+pub trait Filterable {
+    const FILTER_FIELDS: &'static [(&'static str, &'static [FilterOp])];
+
+    fn filter_fields() -> FilterFields {
+        FilterFields::from(
+            Self::FILTER_FIELDS
+                .iter()
+                .map(|&(name, ops)| FilterField::new(name).ops(ops)),
+        )
+    }
+}
+
+pub trait Projectable {
+    const PROJECTION_FIELDS: &'static [&'static str];
+
+    fn projection_fields() -> ProjectionFields {
+        ProjectionFields::from(Self::PROJECTION_FIELDS.iter().copied())
+    }
 }
 
 pub trait MongoFilter {
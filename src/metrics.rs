@@ -0,0 +1,70 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, Encoder, HistogramVec, IntCounterVec,
+    TextEncoder,
+};
+
+//== labels are limited to route templates, methods, status codes, error
+//== codes and fixed operation names so series counts stay bounded
+lazy_static! {
+    pub static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "http_requests_total",
+        "HTTP requests by method, route template and status",
+        &["method", "route", "status"]
+    )
+    .unwrap();
+    pub static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "http_request_duration_seconds",
+        "HTTP request latency by method, route template and status",
+        &["method", "route", "status"]
+    )
+    .unwrap();
+    pub static ref REQUEST_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "request_errors_total",
+        "RequestError responses by error code",
+        &["code"]
+    )
+    .unwrap();
+    pub static ref MONGO_OPERATION_DURATION: HistogramVec = register_histogram_vec!(
+        "mongo_operation_duration_seconds",
+        "Mongo operation latency by collection and operation",
+        &["collection", "operation"]
+    )
+    .unwrap();
+    pub static ref JWKS_CACHE_LOOKUPS: IntCounterVec = register_int_counter_vec!(
+        "jwks_cache_lookups_total",
+        "JWKS cache lookups by result: hit, miss or unknown_kid",
+        &["result"]
+    )
+    .unwrap();
+    pub static ref JWKS_REFRESHES: IntCounterVec = register_int_counter_vec!(
+        "jwks_refreshes_total",
+        "JWKS fetches from the issuer by result: success or failure",
+        &["result"]
+    )
+    .unwrap();
+}
+
+/// Methods outside this list are reported as `OTHER`
+const METHODS: &[&str] = &[
+    "GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS", "TRACE", "CONNECT",
+];
+
+pub fn method_label(method: &str) -> &str {
+    if METHODS.contains(&method) {
+        method
+    } else {
+        "OTHER"
+    }
+}
+
+///
+/// Renders every registered metric in the prometheus text format
+///
+pub fn encode() -> Result<(String, Vec<u8>), prometheus::Error> {
+    let encoder = TextEncoder::new();
+    let mut buffer = vec![];
+    encoder.encode(&prometheus::gather(), &mut buffer)?;
+
+    Ok((encoder.format_type().to_string(), buffer))
+}
@@ -1,27 +1,53 @@
-use actix_web::web;
-use mongodb::{Collection, Database};
+use mongodb::bson::{oid::ObjectId, DateTime};
 use serde::{Deserialize, Serialize};
 
-use crate::MongoCollection;
+use crate::{Filterable, MongoCollection, Projectable, Sortable};
 
 ///
 /// User Model
 ///
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, MongoCollection, Sortable, Filterable, Projectable)]
+#[mongo(collection = "users")]
 pub struct User {
+    #[sortable]
+    #[filterable]
+    #[projectable]
     pub first_name: String,
+
+    #[sortable]
+    #[filterable]
+    #[projectable]
     pub last_name: String,
+
+    #[sortable]
+    #[filterable(ops = "eq,in,contains")]
+    #[projectable]
     pub email: String,
+
+    #[filterable(ops = "gt,gte,lt,lte")]
+    #[projectable]
     pub last_login: String,
 }
 
-impl MongoCollection for User {
-    fn collection_name() -> &'static str {
-        "users"
-    }
-
-    fn collection<T>(db: &web::Data<Database>) -> Collection<T> {
-        db.collection(Self::collection_name())
-    }
+///
+/// ApiKey Model
+///
+/// Only the sha256 `hash` of a key is stored, `prefix` is the
+/// unique, non secret part of the key used to look it up.
+///
+#[derive(Debug, Clone, Serialize, Deserialize, MongoCollection)]
+#[mongo(collection = "api_keys")]
+pub struct ApiKey {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub name: String,
+    pub prefix: String,
+    pub hash: String,
+    pub owner: String,
+    pub scopes: Vec<String>,
+    pub created: DateTime,
+    pub expires: Option<DateTime>,
+    pub last_used: Option<DateTime>,
+    pub revoked: Option<DateTime>,
 }
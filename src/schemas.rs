@@ -1,9 +1,17 @@
 use futures::TryStreamExt;
-use mongodb::Cursor;
+use mongodb::{
+    bson::{self, doc, DateTime, Document},
+    Cursor,
+};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use validator::Validate;
 
-use crate::RequestError;
+use crate::{
+    fields::{PageCursor, Projection, ProjectionFields},
+    models::ApiKey,
+    web::QueryValues,
+    RequestError,
+};
 
 ///
 /// UserOut Schema
@@ -16,47 +24,173 @@ pub struct UserOut {
     pub last_login: String,
 }
 
+///
+/// ApiKeyOut Schema
+///
+/// Stored key metadata, `key` is only set in the response creating it
+///
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyOut {
+    pub id: Option<String>,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created: Option<String>,
+    pub expires: Option<String>,
+    pub last_used: Option<String>,
+    pub revoked: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl From<ApiKey> for ApiKeyOut {
+    fn from(api_key: ApiKey) -> Self {
+        let rfc3339 = |date: DateTime| date.try_to_rfc3339_string().ok();
+
+        Self {
+            id: api_key.id.map(|id| id.to_hex()),
+            name: api_key.name,
+            prefix: api_key.prefix,
+            scopes: api_key.scopes,
+            created: rfc3339(api_key.created),
+            expires: api_key.expires.and_then(rfc3339),
+            last_used: api_key.last_used.and_then(rfc3339),
+            revoked: api_key.revoked.and_then(rfc3339),
+            key: None,
+        }
+    }
+}
+
 ///
 /// Page (Pagination) Schema
 ///
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Page<T: Sized> {
     pub count: usize,
+    pub total: Option<u64>,
     pub items: Vec<T>,
+    pub first: i64,
+    pub prev: Option<i64>,
     pub next: Option<i64>,
+    pub last: Option<i64>,
+    pub next_cursor: Option<String>,
 }
 
 impl<T: Sized> Page<T> {
     pub fn new() -> Self {
         Self {
             count: 0,
+            total: None,
             items: vec![],
+            first: 0,
+            prev: None,
             next: None,
+            last: None,
+            next_cursor: None,
+        }
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            count: self.count,
+            total: self.total,
+            items: self.items.into_iter().map(f).collect(),
+            first: self.first,
+            prev: self.prev,
+            next: self.next,
+            last: self.last,
+            next_cursor: self.next_cursor,
         }
     }
 }
 
+impl<T: Sized> Default for Page<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct PageBuilder {
     pub offset: i64,
     pub limit: i64,
+    pub total: Option<u64>,
+    pub sort: Option<Document>,
+    pub keyset: bool,
 }
 
 impl PageBuilder {
-    pub async fn build<T>(self, cursor: Cursor<T>) -> Result<Page<T>, RequestError>
+    pub fn total(mut self, total: Option<u64>) -> Self {
+        self.total = total;
+        self
+    }
+
+    /// Keyset sort used to build `next_cursor` from the last item
+    pub fn sort(mut self, sort: Document) -> Self {
+        self.sort = Some(sort);
+        self
+    }
+
+    pub async fn build<T>(self, cursor: Cursor<Document>) -> Result<Page<T>, RequestError>
     where
-        T: DeserializeOwned + Sync + Unpin + Send,
+        T: DeserializeOwned,
     {
-        let items: Vec<T> = cursor.try_collect().await?;
-        let next = if items.len() < self.limit as usize {
-            None
+        let docs: Vec<Document> = cursor.try_collect().await?;
+
+        let next_cursor = match (&self.sort, docs.last()) {
+            (Some(sort), Some(last)) if docs.len() >= self.limit as usize => {
+                PageCursor::from_document(last, sort).map(|c| c.encode())
+            }
+            _ => None,
+        };
+
+        let items = docs
+            .into_iter()
+            .map(bson::from_document)
+            .collect::<Result<Vec<T>, _>>()?;
+
+        //== offsets are meaningless while paging by cursor
+        if self.keyset {
+            return Ok(Page {
+                count: items.len(),
+                total: self.total,
+                items,
+                first: 0,
+                prev: None,
+                next: None,
+                last: None,
+                next_cursor,
+            });
+        }
+
+        let next = match self.total {
+            Some(total) if self.offset + self.limit >= total as i64 => None,
+            Some(_) => Some(self.offset + self.limit),
+            None if items.len() < self.limit as usize => None,
+            None => Some(self.offset + self.limit),
+        };
+
+        let prev = if self.offset > 0 {
+            Some((self.offset - self.limit).max(0))
         } else {
-            Some(self.offset + self.limit)
+            None
         };
 
+        //== offset of the final page, only known with a total count
+        let last = self
+            .total
+            .filter(|_| self.limit > 0)
+            .map(|total| (total.saturating_sub(1) as i64 / self.limit) * self.limit);
+
         Ok(Page {
             count: items.len(),
-            next: next,
-            items: items,
+            total: self.total,
+            items,
+            first: 0,
+            prev,
+            next,
+            last,
+            next_cursor,
         })
     }
 }
@@ -66,6 +200,9 @@ impl From<&PageParams> for PageBuilder {
         Self {
             limit: params.limit,
             offset: params.offset,
+            total: None,
+            sort: None,
+            keyset: params.cursor.is_some(),
         }
     }
 }
@@ -73,15 +210,62 @@ impl From<&PageParams> for PageBuilder {
 #[derive(Serialize, Deserialize, Validate)]
 pub struct PageParams {
     #[validate(range(min = 1, max = 1000))]
-    #[serde(default = "PageParams::default_limit")]
+    #[serde(
+        default = "PageParams::default_limit",
+        deserialize_with = "crate::web::from_str"
+    )]
     pub limit: i64,
 
     #[validate(range(min = 0))]
-    #[serde(default = "PageParams::default_offset")]
+    #[serde(
+        default = "PageParams::default_offset",
+        deserialize_with = "crate::web::from_str"
+    )]
     pub offset: i64,
+
+    /// Opaque keyset token from a previous page's `next_cursor`.
+    /// Takes precedence over `offset` when present.
+    pub cursor: Option<String>,
+
+    /// Counts matching documents for `total` while paging by cursor
+    #[serde(default, deserialize_with = "crate::web::from_str")]
+    pub count: bool,
 }
 
 impl PageParams {
+    pub fn skip(&self) -> Option<u64> {
+        match self.cursor {
+            Some(_) => None,
+            None => Some(self.offset as u64),
+        }
+    }
+
+    ///
+    /// Whether `total` is wanted, always with offsets which need it for `last`
+    ///
+    pub fn counts(&self) -> bool {
+        self.count || self.cursor.is_none()
+    }
+
+    ///
+    /// Combines `filter` with the keyset filter of the page cursor, if any
+    ///
+    pub fn mongo_filter(
+        &self,
+        filter: Option<Document>,
+        sort: &Document,
+    ) -> Result<Option<Document>, RequestError> {
+        let keyset = match self.cursor {
+            Some(ref token) => PageCursor::decode(token)?.mongo_filter(sort)?,
+            None => return Ok(filter),
+        };
+
+        Ok(Some(match filter {
+            Some(filter) => doc! { "$and": [filter, keyset] },
+            None => keyset,
+        }))
+    }
+
     pub fn default_limit() -> i64 {
         100
     }
@@ -89,3 +273,21 @@ impl PageParams {
         0
     }
 }
+
+///
+/// Sparse fieldset params, `fields=first_name,email` or `exclude=last_login`
+///
+#[derive(Serialize, Deserialize, Validate)]
+pub struct ProjectionParams {
+    pub fields: Option<QueryValues>,
+    pub exclude: Option<QueryValues>,
+}
+
+impl ProjectionParams {
+    pub fn projection(
+        &self,
+        fields: &ProjectionFields,
+    ) -> Result<Option<Projection>, RequestError> {
+        fields.projection(self.fields.as_deref(), self.exclude.as_deref())
+    }
+}
@@ -0,0 +1,22 @@
+use tracing_subscriber::EnvFilter;
+
+pub use api_settings::LogFormat;
+
+///
+/// Installs the global tracing subscriber. `RUST_LOG` overrides `filter`,
+/// records of crates using `log` are forwarded as tracing events.
+///
+pub fn init(format: LogFormat, filter: &str) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(filter));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match format {
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+        LogFormat::Pretty => builder.pretty().init(),
+    }
+}
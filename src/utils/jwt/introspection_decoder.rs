@@ -0,0 +1,304 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use jsonwebtoken::{errors::ErrorKind, Header, TokenData, Validation};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use super::{DecodeInfo, Decoder};
+use crate::utils::oauth::{http, Error};
+
+/// cache entries are pruned once the cache grows past this size
+const MAX_CACHE_ENTRIES: usize = 10_000;
+
+struct CachedResponse {
+    expires: Instant,
+    response: Option<Map<String, Value>>,
+}
+
+///
+/// IntrospectionDecoder
+///
+/// Verifies opaque access tokens with an RFC 7662 introspection endpoint.
+/// Active responses are cached until `exp`, inactive ones for `inactive_ttl`,
+/// and responses are mapped onto the same claims as decoded JWTs.
+///
+pub struct IntrospectionDecoder {
+    endpoint: String,
+    client_id: String,
+    client_secret: String,
+    client: reqwest::Client,
+    max_ttl: Duration,
+    inactive_ttl: Duration,
+    cache: RwLock<HashMap<String, CachedResponse>>,
+}
+
+impl IntrospectionDecoder {
+    pub fn new(
+        endpoint: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            client: http::client(),
+            max_ttl: Duration::from_secs(300),
+            inactive_ttl: Duration::from_secs(60),
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Longest time an active response is cached, regardless of `exp`
+    pub fn max_ttl(mut self, ttl: Duration) -> Self {
+        self.max_ttl = ttl;
+        self
+    }
+
+    /// Time an inactive response is cached
+    pub fn inactive_ttl(mut self, ttl: Duration) -> Self {
+        self.inactive_ttl = ttl;
+        self
+    }
+
+    ///
+    /// Returns the introspection response of an active token, or `None`
+    ///
+    async fn introspect(&self, token: &str) -> Result<Option<Map<String, Value>>, Error> {
+        //== tokens are cached by digest, never in the clear
+        let key = format!("{:x}", Sha256::digest(token.as_bytes()));
+
+        if let Some(cached) = self.cache.read().unwrap().get(&key) {
+            if cached.expires > Instant::now() {
+                return Ok(cached.response.clone());
+            }
+        }
+
+        let request = self
+            .client
+            .post(&self.endpoint)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&[("token", token), ("token_type_hint", "access_token")]);
+
+        let mut response: Map<String, Value> = http::send_json(&self.endpoint, request).await?;
+
+        let active = response.remove("active").and_then(|a| a.as_bool()) == Some(true);
+        let (response, ttl) = if active {
+            let ttl = response
+                .get("exp")
+                .and_then(Value::as_u64)
+                .map(|exp| Duration::from_secs(exp.saturating_sub(now())))
+                .map(|ttl| ttl.min(self.max_ttl))
+                .unwrap_or(self.max_ttl);
+
+            (Some(response), ttl)
+        } else {
+            (None, self.inactive_ttl)
+        };
+
+        let mut cache = self.cache.write().unwrap();
+        if cache.len() >= MAX_CACHE_ENTRIES {
+            let now = Instant::now();
+            cache.retain(|_, cached| cached.expires > now);
+        }
+
+        cache.insert(
+            key,
+            CachedResponse {
+                expires: Instant::now() + ttl,
+                response: response.clone(),
+            },
+        );
+
+        Ok(response)
+    }
+}
+
+#[async_trait(?Send)]
+impl Decoder for IntrospectionDecoder {
+    ///
+    /// Introspection results carry no JWT header, a default one is returned
+    ///
+    async fn decode<T>(&self, info: &DecodeInfo<'_>) -> Result<TokenData<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let mut response = self
+            .introspect(info.token)
+            .await?
+            .ok_or(Error::InactiveToken)?;
+
+        validate(&response, info.validation)?;
+
+        //== client credential tokens have no subject, use the client
+        if !response.contains_key("sub") {
+            if let Some(client_id) = response.get("client_id").cloned() {
+                response.insert("sub".into(), client_id);
+            }
+        }
+
+        let claims = serde_json::from_value(Value::Object(response))
+            .map_err(|e| Error::Jwt(ErrorKind::Json(e.into()).into()))?;
+
+        Ok(TokenData {
+            header: Header::default(),
+            claims,
+        })
+    }
+}
+
+///
+/// Applies the issuer, audience and expiry checks of `validation`
+///
+fn validate(response: &Map<String, Value>, validation: &Validation) -> Result<(), Error> {
+    if let Some(ref issuers) = validation.iss {
+        match response.get("iss").and_then(Value::as_str) {
+            Some(iss) if issuers.contains(iss) => {}
+            _ => return Err(Error::Jwt(ErrorKind::InvalidIssuer.into())),
+        }
+    }
+
+    if let Some(ref audiences) = validation.aud {
+        let found = match response.get("aud") {
+            Some(Value::String(aud)) => audiences.contains(aud),
+            Some(Value::Array(auds)) => auds
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|aud| audiences.contains(aud)),
+            _ => false,
+        };
+
+        if !found {
+            return Err(Error::Jwt(ErrorKind::InvalidAudience.into()));
+        }
+    }
+
+    if let Some(exp) = response.get("exp").and_then(Value::as_u64) {
+        if validation.validate_exp && exp + validation.leeway < now() {
+            return Err(Error::Jwt(ErrorKind::ExpiredSignature.into()));
+        }
+    }
+
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{auth::Claims, utils::jwt::JwtDecoder};
+
+    #[derive(serde::Deserialize)]
+    struct IntrospectionForm {
+        token: String,
+    }
+
+    ///
+    /// Starts a mock introspection endpoint, returning its uri and call counter
+    ///
+    fn mock_server() -> (String, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let data = web::Data::from(calls.clone());
+
+        let server = HttpServer::new(move || {
+            App::new().app_data(data.clone()).route(
+                "/introspect",
+                web::post().to(
+                    |form: web::Form<IntrospectionForm>, calls: web::Data<AtomicUsize>| async move {
+                        calls.fetch_add(1, Ordering::SeqCst);
+
+                        let body = match form.token.as_str() {
+                            "active-token" => json!({
+                                "active": true,
+                                "sub": "user-1",
+                                "scope": "users:read users:write",
+                                "exp": now() + 60,
+                            }),
+                            "client-token" => json!({
+                                "active": true,
+                                "client_id": "service-1",
+                                "exp": now() + 60,
+                            }),
+                            _ => json!({ "active": false }),
+                        };
+
+                        HttpResponse::Ok().json(body)
+                    },
+                ),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+
+        let uri = format!("http://{}/introspect", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+
+        (uri, calls)
+    }
+
+    fn decoder(uri: String) -> JwtDecoder<IntrospectionDecoder> {
+        JwtDecoder::new(
+            IntrospectionDecoder::new(uri, "client", "secret"),
+            Validation::default(),
+        )
+    }
+
+    #[actix_web::test]
+    async fn active_token_maps_to_claims() {
+        let (uri, _) = mock_server();
+
+        let claims = decoder(uri).decode::<Claims>("active-token").await.unwrap().claims;
+        assert_eq!(claims.sub, "user-1");
+        assert_eq!(claims.scopes(), ["users:read", "users:write"]);
+    }
+
+    #[actix_web::test]
+    async fn client_id_is_subject_without_sub() {
+        let (uri, _) = mock_server();
+
+        let claims = decoder(uri).decode::<Claims>("client-token").await.unwrap().claims;
+        assert_eq!(claims.sub, "service-1");
+    }
+
+    #[actix_web::test]
+    async fn inactive_token_is_rejected() {
+        let (uri, _) = mock_server();
+
+        let result = decoder(uri).decode::<Claims>("revoked-token").await;
+        assert!(matches!(result, Err(Error::InactiveToken)));
+    }
+
+    #[actix_web::test]
+    async fn results_are_cached() {
+        let (uri, calls) = mock_server();
+        let decoder = decoder(uri);
+
+        for _ in 0..3 {
+            decoder.decode::<Claims>("active-token").await.unwrap();
+            assert!(decoder.decode::<Claims>("revoked-token").await.is_err());
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
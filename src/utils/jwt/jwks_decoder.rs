@@ -1,62 +1,67 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
-use jsonwebtoken::{
-    errors::{Error, ErrorKind},
-    Algorithm, DecodingKey, TokenData,
-};
+use jsonwebtoken::{errors::ErrorKind, Algorithm, DecodingKey, Header, TokenData};
 use serde::de::DeserializeOwned;
-use std::{
-    cell::RefCell,
-    rc::Rc,
-    time::{Duration, Instant},
-};
-
-use crate::utils::oauth::models::Jwks;
 
-use super::{DecodeInfo, Decoder};
+use super::{jwks_provider::JwksProvider, DecodeInfo, Decoder};
+use crate::utils::oauth::{
+    models::{Jwk, JwkKey},
+    Error,
+};
 
 pub struct JwksDecoder {
-    jwks_provider: JwksProvider,
+    jwks_provider: Arc<JwksProvider>,
     secret: Option<String>,
 }
 
 impl JwksDecoder {
-    async fn decode_rsa<T>(&self, info: &DecodeInfo<'_>) -> Result<TokenData<T>, Error>
+    pub fn new(jwks_provider: impl Into<Arc<JwksProvider>>) -> Self {
+        Self {
+            jwks_provider: jwks_provider.into(),
+            secret: None,
+        }
+    }
+
+    /// Shared secret used to verify HS* signed tokens
+    pub fn secret(mut self, secret: impl Into<String>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    async fn decode_jwk<T>(
+        &self,
+        info: &DecodeInfo<'_>,
+        header: &Header,
+    ) -> Result<TokenData<T>, Error>
     where
         T: DeserializeOwned,
     {
-        let kid = info
-            .header
-            .kid
-            .as_ref()
-            .ok_or(Error::from(ErrorKind::InvalidKeyFormat))?;
+        let kid = header.kid.as_ref().ok_or(Error::MissingKid)?;
 
-        let jwks = self
-            .jwks_provider
-            .jwks()
-            .await
-            .map_err(|_| Error::from(ErrorKind::InvalidKeyFormat))?;
+        let jwks = self.jwks_provider.jwks_for_kid(kid).await?;
 
         let jwk = jwks
-            .jwk(&kid)
-            .ok_or(Error::from(ErrorKind::InvalidKeyFormat))?;
+            .jwk(kid)
+            .ok_or_else(|| Error::UnknownKid(kid.clone()))?;
 
-        let key = DecodingKey::from_rsa_components(&jwk.n, &jwk.e);
+        let key = decoding_key(jwk, header.alg)?;
 
-        jsonwebtoken::decode(&info.token, &key, &info.validation)
+        Ok(jsonwebtoken::decode(info.token, &key, info.validation)?)
     }
 
-    fn decode_hsa<T>(&self, info: &DecodeInfo<'_>) -> Result<TokenData<T>, Error>
+    fn decode_hsa<T>(&self, info: &DecodeInfo<'_>, header: &Header) -> Result<TokenData<T>, Error>
     where
         T: DeserializeOwned,
     {
         let secret = self
             .secret
             .as_ref()
-            .ok_or(Error::from(ErrorKind::InvalidKeyFormat))?;
+            .ok_or(Error::UnsupportedAlg(header.alg))?;
 
         let key = DecodingKey::from_secret(secret.as_bytes());
 
-        jsonwebtoken::decode(&info.token, &key, &info.validation)
+        Ok(jsonwebtoken::decode(info.token, &key, info.validation)?)
     }
 }
 
@@ -66,66 +71,217 @@ impl Decoder for JwksDecoder {
     where
         T: DeserializeOwned,
     {
-        match info.header.alg {
-            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => self.decode_rsa(&info).await,
-            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => self.decode_hsa(&info),
-            _ => Err(Error::from(ErrorKind::InvalidAlgorithm)),
+        let header = info
+            .header
+            .ok_or(Error::Jwt(ErrorKind::InvalidToken.into()))?;
+
+        match header.alg {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => self.decode_hsa(info, header),
+            _ => self.decode_jwk(info, header).await,
         }
     }
 }
 
-pub struct JwksProviderCache {
-    ttl: Duration,
-    instant: Instant,
-    value: Option<Rc<Jwks>>,
+///
+/// Builds the verification key for `alg`, checking that the jwk's
+/// `alg`, `use` and key type are compatible with the token header
+///
+fn decoding_key(jwk: &Jwk, alg: Algorithm) -> Result<DecodingKey, Error> {
+    if let Some(ref jwk_alg) = jwk.alg {
+        if jwk_alg.parse::<Algorithm>().ok() != Some(alg) {
+            return Err(Error::InvalidKey(format!(
+                "key algorithm {} does not match token algorithm {:?}",
+                jwk_alg, alg
+            )));
+        }
+    }
+
+    if let Some(ref key_use) = jwk.key_use {
+        if key_use != "sig" {
+            return Err(Error::InvalidKey(format!(
+                "key use {} is not a signature key",
+                key_use
+            )));
+        }
+    }
+
+    let key = match (&jwk.key, alg) {
+        (
+            JwkKey::Rsa { n, e },
+            Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512,
+        ) => DecodingKey::from_rsa_components(n, e),
+        (JwkKey::Ec { crv, x, y }, Algorithm::ES256) if crv == "P-256" => {
+            DecodingKey::from_ec_components(x, y)
+        }
+        (JwkKey::Ec { crv, x, y }, Algorithm::ES384) if crv == "P-384" => {
+            DecodingKey::from_ec_components(x, y)
+        }
+        (JwkKey::Okp { crv, x }, Algorithm::EdDSA) if crv == "Ed25519" => {
+            DecodingKey::from_ed_components(x)
+        }
+        (JwkKey::Unsupported, _) => return Err(Error::UnsupportedAlg(alg)),
+        _ => {
+            return Err(Error::InvalidKey(format!(
+                "key type does not support algorithm {:?}",
+                alg
+            )))
+        }
+    };
+
+    Ok(key?)
 }
 
-impl JwksProviderCache {
-    pub fn expired(&self) -> bool {
-        self.value.is_none() || self.ttl.is_zero() || self.instant.elapsed() > self.ttl
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use jsonwebtoken::{EncodingKey, Validation};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::utils::jwt::JwtDecoder;
+
+    //== throwaway keys generated for these tests, `testdata/jwks.json`
+    //== holds their public halves with `alg` and `use` set
+    const JWKS: &str = include_str!("testdata/jwks.json");
+    const ES256_KEY: &str = include_str!("testdata/es256.pem");
+    const ES384_KEY: &str = include_str!("testdata/es384.pem");
+    const PS256_KEY: &str = include_str!("testdata/ps256.pem");
+    const EDDSA_KEY: &str = include_str!("testdata/eddsa.pem");
+
+    ///
+    /// Starts a mock jwks endpoint serving the test keys, returning its uri
+    ///
+    fn mock_server() -> String {
+        let server = HttpServer::new(|| {
+            App::new().route(
+                "/jwks",
+                web::get().to(|| async {
+                    HttpResponse::Ok()
+                        .content_type("application/json")
+                        .body(JWKS)
+                }),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+
+        let uri = format!("http://{}/jwks", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+        uri
     }
 
-    pub fn update(&mut self, jwks: Rc<Jwks>) -> Rc<Jwks> {
-        self.value = Some(jwks.clone());
-        jwks
+    fn encoding_key(alg: Algorithm) -> EncodingKey {
+        match alg {
+            Algorithm::ES256 => EncodingKey::from_ec_pem(ES256_KEY.as_bytes()),
+            Algorithm::ES384 => EncodingKey::from_ec_pem(ES384_KEY.as_bytes()),
+            Algorithm::PS256 => EncodingKey::from_rsa_pem(PS256_KEY.as_bytes()),
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(EDDSA_KEY.as_bytes()),
+            _ => unreachable!("no test key for {:?}", alg),
+        }
+        .unwrap()
     }
 
-    pub fn value(&self) -> Option<Rc<Jwks>> {
-        self.value.clone()
+    fn token(alg: Algorithm, kid: &str) -> String {
+        let exp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 60;
+        let mut header = Header::new(alg);
+        header.kid = Some(kid.into());
+
+        jsonwebtoken::encode(
+            &header,
+            &json!({ "sub": "user-1", "exp": exp }),
+            &encoding_key(alg),
+        )
+        .unwrap()
     }
-}
 
-pub struct JwksProvider {
-    uri: String,
-    cache: RefCell<JwksProviderCache>,
-}
+    async fn decode(alg: Algorithm, kid: &str) -> Result<TokenData<Value>, Error> {
+        let decoder = JwtDecoder::new(
+            JwksDecoder::new(JwksProvider::default(mock_server())),
+            Validation::new(alg),
+        );
+        decoder.decode(&token(alg, kid)).await
+    }
 
-impl JwksProvider {
-    pub fn new(uri: impl AsRef<str>, cache_ttl: Duration) -> Self {
-        Self {
-            uri: String::from(uri.as_ref()),
-            cache: RefCell::new(JwksProviderCache {
-                ttl: cache_ttl,
-                instant: Instant::now(),
-                value: None,
-            }),
-        }
+    #[actix_web::test]
+    async fn es256_round_trip() {
+        let data = decode(Algorithm::ES256, "es256").await.unwrap();
+        assert_eq!(data.claims["sub"], "user-1");
     }
 
-    pub fn default(uri: impl AsRef<str>) -> Self {
-        Self::new(uri, Duration::from_secs(3600))
+    #[actix_web::test]
+    async fn es384_round_trip() {
+        let data = decode(Algorithm::ES384, "es384").await.unwrap();
+        assert_eq!(data.claims["sub"], "user-1");
     }
 
-    pub async fn jwks(&self) -> Result<Rc<Jwks>, String> {
-        let cache = self.cache.borrow();
+    #[actix_web::test]
+    async fn ps256_round_trip() {
+        let data = decode(Algorithm::PS256, "ps256").await.unwrap();
+        assert_eq!(data.claims["sub"], "user-1");
+    }
 
-        if cache.expired() {
-            let mut cache = self.cache.borrow_mut();
+    #[actix_web::test]
+    async fn eddsa_round_trip() {
+        let data = decode(Algorithm::EdDSA, "eddsa").await.unwrap();
+        assert_eq!(data.claims["sub"], "user-1");
+    }
 
-            let jwks = Rc::new(Jwks::from_uri(&self.uri).await?);
-            return Ok(cache.update(jwks));
-        } else {
-            return Ok(cache.value().ok_or("no value")?);
-        }
+    #[actix_web::test]
+    async fn key_for_another_algorithm_is_rejected() {
+        //== signed with the ES256 key but naming the ES384 one
+        let error = decode(Algorithm::ES256, "es384").await.err().unwrap();
+        assert!(matches!(error, Error::InvalidKey(_)));
+    }
+
+    #[test]
+    fn key_with_mismatched_alg_is_rejected() {
+        let jwk: Jwk = serde_json::from_value(json!({
+            "kid": "k1", "alg": "PS256", "use": "sig", "kty": "RSA", "n": "AQAB", "e": "AQAB"
+        }))
+        .unwrap();
+
+        assert!(decoding_key(&jwk, Algorithm::PS256).is_ok());
+        assert!(matches!(
+            decoding_key(&jwk, Algorithm::RS256),
+            Err(Error::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn encryption_key_is_rejected() {
+        let jwk: Jwk = serde_json::from_value(json!({
+            "kid": "k1", "use": "enc", "kty": "OKP", "crv": "Ed25519", "x": "AQAB"
+        }))
+        .unwrap();
+
+        assert!(matches!(
+            decoding_key(&jwk, Algorithm::EdDSA),
+            Err(Error::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn curve_must_match_algorithm() {
+        let jwk: Jwk = serde_json::from_value(json!({
+            "kid": "k1", "kty": "EC", "crv": "P-256", "x": "AQAB", "y": "AQAB"
+        }))
+        .unwrap();
+
+        assert!(matches!(
+            decoding_key(&jwk, Algorithm::ES384),
+            Err(Error::InvalidKey(_))
+        ));
     }
 }
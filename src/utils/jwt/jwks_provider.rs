@@ -0,0 +1,331 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use futures::lock::Mutex;
+use tracing::warn;
+
+use crate::{
+    metrics,
+    utils::oauth::{models::Jwks, Error},
+};
+
+struct JwksProviderCache {
+    ttl: Duration,
+    /// time of the last successful fetch
+    instant: Option<Instant>,
+    /// time of the last fetch attempt, successful or not
+    attempt: Option<Instant>,
+    value: Option<Arc<Jwks>>,
+}
+
+impl JwksProviderCache {
+    fn expired(&self) -> bool {
+        match self.instant {
+            Some(instant) => self.value.is_none() || instant.elapsed() > self.ttl,
+            None => true,
+        }
+    }
+
+    fn attempted_within(&self, interval: Duration) -> bool {
+        self.attempt
+            .map(|attempt| attempt.elapsed() < interval)
+            .unwrap_or(false)
+    }
+
+    /// The last fetch attempt failed less than `interval` ago
+    fn failed_within(&self, interval: Duration) -> bool {
+        self.attempt != self.instant && self.attempted_within(interval)
+    }
+
+    fn update(&mut self, jwks: Arc<Jwks>) -> Arc<Jwks> {
+        let now = Instant::now();
+        self.instant = Some(now);
+        self.attempt = Some(now);
+        self.value = Some(jwks.clone());
+        jwks
+    }
+}
+
+///
+/// JwksProvider
+///
+/// Thread safe JWKS cache shared across workers. Refreshes are single-flight,
+/// an unknown `kid` triggers a rate limited refetch, and stale keys are
+/// served when the issuer is unreachable.
+///
+pub struct JwksProvider {
+    uri: String,
+    min_refresh_interval: Duration,
+    cache: RwLock<JwksProviderCache>,
+    refresh: Mutex<()>,
+}
+
+impl JwksProvider {
+    pub fn new(uri: impl AsRef<str>, cache_ttl: Duration) -> Self {
+        Self {
+            uri: String::from(uri.as_ref()),
+            min_refresh_interval: Duration::from_secs(60),
+            cache: RwLock::new(JwksProviderCache {
+                ttl: cache_ttl,
+                instant: None,
+                attempt: None,
+                value: None,
+            }),
+            refresh: Mutex::new(()),
+        }
+    }
+
+    pub fn default(uri: impl AsRef<str>) -> Self {
+        Self::new(uri, Duration::from_secs(3600))
+    }
+
+    /// Minimum time between forced refetches (unknown `kid`, failed fetches)
+    pub fn min_refresh_interval(mut self, interval: Duration) -> Self {
+        self.min_refresh_interval = interval;
+        self
+    }
+
+    pub async fn jwks(&self) -> Result<Arc<Jwks>, Error> {
+        {
+            let cache = self.cache.read().unwrap();
+            if let (false, Some(value)) = (cache.expired(), cache.value.as_ref()) {
+                metrics::JWKS_CACHE_LOOKUPS
+                    .with_label_values(&["hit"])
+                    .inc();
+                return Ok(value.clone());
+            }
+        }
+
+        metrics::JWKS_CACHE_LOOKUPS
+            .with_label_values(&["miss"])
+            .inc();
+
+        self.refresh(false).await
+    }
+
+    ///
+    /// Returns keys containing `kid`, refetching once if it is unknown
+    ///
+    pub async fn jwks_for_kid(&self, kid: impl AsRef<str>) -> Result<Arc<Jwks>, Error> {
+        let jwks = self.jwks().await?;
+
+        if jwks.jwk(kid.as_ref()).is_some() {
+            return Ok(jwks);
+        }
+
+        metrics::JWKS_CACHE_LOOKUPS
+            .with_label_values(&["unknown_kid"])
+            .inc();
+
+        self.refresh(true).await
+    }
+
+    ///
+    /// Keys are cached, or can be fetched when none are. Meant for
+    /// readiness checks, which must not refetch on every probe.
+    ///
+    pub async fn ready(&self) -> Result<(), Error> {
+        if self.cache.read().unwrap().value.is_some() {
+            return Ok(());
+        }
+
+        self.refresh(false).await.map(|_| ())
+    }
+
+    ///
+    /// Fetches the key set, unless another caller refreshed it while this
+    /// one waited. `force` refetches fresh keys, subject to rate limiting.
+    ///
+    async fn refresh(&self, force: bool) -> Result<Arc<Jwks>, Error> {
+        let _guard = self.refresh.lock().await;
+
+        {
+            let cache = self.cache.read().unwrap();
+            let rate_limited = cache.attempted_within(self.min_refresh_interval);
+
+            if let Some(ref value) = cache.value {
+                let fresh = !cache.expired();
+
+                //== serve stale keys while the issuer recently failed
+                if (!force && fresh)
+                    || (force && rate_limited)
+                    || (!fresh && cache.failed_within(self.min_refresh_interval))
+                {
+                    return Ok(value.clone());
+                }
+            } else if rate_limited {
+                return Err(Error::Unavailable {
+                    uri: self.uri.clone(),
+                });
+            }
+        }
+
+        match Jwks::from_uri(&self.uri).await {
+            Ok(jwks) => {
+                metrics::JWKS_REFRESHES
+                    .with_label_values(&["success"])
+                    .inc();
+                Ok(self.cache.write().unwrap().update(Arc::new(jwks)))
+            }
+            Err(e) => {
+                metrics::JWKS_REFRESHES
+                    .with_label_values(&["failure"])
+                    .inc();
+                let mut cache = self.cache.write().unwrap();
+                cache.attempt = Some(Instant::now());
+
+                match cache.value {
+                    Some(ref value) => {
+                        warn!(uri = %self.uri, error = %e, "jwks refresh failed, using stale keys");
+                        Ok(value.clone())
+                    }
+                    None => Err(e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use serde_json::json;
+
+    use super::*;
+
+    struct MockIssuer {
+        calls: AtomicUsize,
+        down: AtomicBool,
+    }
+
+    ///
+    /// Starts a mock jwks endpoint serving key `k1`, returning its uri and
+    /// state. Requests fail with 503 while `down` is set.
+    ///
+    fn mock_server() -> (String, Arc<MockIssuer>) {
+        let issuer = Arc::new(MockIssuer {
+            calls: AtomicUsize::new(0),
+            down: AtomicBool::new(false),
+        });
+        let data = web::Data::from(issuer.clone());
+
+        let server = HttpServer::new(move || {
+            App::new().app_data(data.clone()).route(
+                "/jwks",
+                web::get().to(|issuer: web::Data<MockIssuer>| async move {
+                    issuer.calls.fetch_add(1, Ordering::SeqCst);
+
+                    if issuer.down.load(Ordering::SeqCst) {
+                        return HttpResponse::ServiceUnavailable().finish();
+                    }
+
+                    HttpResponse::Ok().json(json!({
+                        "keys": [{ "kid": "k1", "kty": "RSA", "n": "AQAB", "e": "AQAB" }]
+                    }))
+                }),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+
+        let uri = format!("http://{}/jwks", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+
+        (uri, issuer)
+    }
+
+    fn calls(issuer: &MockIssuer) -> usize {
+        issuer.calls.load(Ordering::SeqCst)
+    }
+
+    #[actix_web::test]
+    async fn concurrent_misses_fetch_once() {
+        let (uri, issuer) = mock_server();
+        let provider = JwksProvider::default(uri);
+
+        let results = futures::future::join_all((0..5).map(|_| provider.jwks())).await;
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(calls(&issuer), 1);
+    }
+
+    #[actix_web::test]
+    async fn unknown_kid_refetch_is_rate_limited() {
+        let (uri, issuer) = mock_server();
+        let provider = JwksProvider::default(uri);
+
+        provider.jwks().await.unwrap();
+        provider.jwks_for_kid("k2").await.unwrap();
+        provider.jwks_for_kid("k2").await.unwrap();
+        assert_eq!(calls(&issuer), 1);
+    }
+
+    #[actix_web::test]
+    async fn expired_keys_are_served_stale_while_issuer_is_down() {
+        let (uri, issuer) = mock_server();
+        let provider = JwksProvider::new(uri, Duration::ZERO);
+
+        provider.jwks().await.unwrap();
+        issuer.down.store(true, Ordering::SeqCst);
+
+        //== first call after expiry tries the issuer, later ones don't
+        for _ in 0..3 {
+            let jwks = provider.jwks().await.unwrap();
+            assert!(jwks.jwk("k1").is_some());
+        }
+        assert_eq!(calls(&issuer), 2);
+    }
+
+    #[actix_web::test]
+    async fn expired_keys_are_refetched_once_the_interval_passes() {
+        let (uri, issuer) = mock_server();
+        let provider = JwksProvider::new(uri, Duration::ZERO).min_refresh_interval(Duration::ZERO);
+
+        provider.jwks().await.unwrap();
+        issuer.down.store(true, Ordering::SeqCst);
+        provider.jwks().await.unwrap();
+        issuer.down.store(false, Ordering::SeqCst);
+        provider.jwks().await.unwrap();
+        assert_eq!(calls(&issuer), 3);
+    }
+
+    #[actix_web::test]
+    async fn ready_fetches_only_without_cached_keys() {
+        let (uri, issuer) = mock_server();
+        let provider = JwksProvider::new(uri, Duration::ZERO);
+
+        for _ in 0..3 {
+            provider.ready().await.unwrap();
+        }
+        assert_eq!(calls(&issuer), 1);
+    }
+
+    #[actix_web::test]
+    async fn ready_fails_while_no_keys_can_be_fetched() {
+        let (uri, issuer) = mock_server();
+        issuer.down.store(true, Ordering::SeqCst);
+        let provider = JwksProvider::default(uri);
+
+        assert!(provider.ready().await.is_err());
+        assert!(provider.ready().await.is_err());
+        assert_eq!(calls(&issuer), 1);
+    }
+
+    #[actix_web::test]
+    async fn failure_without_keys_is_rate_limited() {
+        let (uri, issuer) = mock_server();
+        issuer.down.store(true, Ordering::SeqCst);
+        let provider = JwksProvider::default(uri);
+
+        assert!(provider.jwks().await.is_err());
+        assert!(matches!(
+            provider.jwks().await,
+            Err(Error::Unavailable { .. })
+        ));
+        assert_eq!(calls(&issuer), 1);
+    }
+}
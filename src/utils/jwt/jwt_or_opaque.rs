@@ -0,0 +1,49 @@
+use async_trait::async_trait;
+use jsonwebtoken::{errors::ErrorKind, TokenData};
+use serde::de::DeserializeOwned;
+
+use super::{DecodeInfo, Decoder};
+use crate::utils::oauth::Error;
+
+///
+/// JwtOrOpaque
+///
+/// Decodes tokens with a header through `jwt` and opaque tokens through
+/// `opaque` (e.g. introspection), rejecting opaque tokens when it is unset.
+///
+pub struct JwtOrOpaque<J, O>
+where
+    J: Decoder,
+    O: Decoder,
+{
+    jwt: J,
+    opaque: Option<O>,
+}
+
+impl<J, O> JwtOrOpaque<J, O>
+where
+    J: Decoder,
+    O: Decoder,
+{
+    pub fn new(jwt: J, opaque: Option<O>) -> Self {
+        Self { jwt, opaque }
+    }
+}
+
+#[async_trait(?Send)]
+impl<J, O> Decoder for JwtOrOpaque<J, O>
+where
+    J: Decoder,
+    O: Decoder,
+{
+    async fn decode<T>(&self, info: &DecodeInfo<'_>) -> Result<TokenData<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        match (info.header, &self.opaque) {
+            (Some(_), _) => self.jwt.decode(info).await,
+            (None, Some(opaque)) => opaque.decode(info).await,
+            (None, None) => Err(Error::Jwt(ErrorKind::InvalidToken.into())),
+        }
+    }
+}
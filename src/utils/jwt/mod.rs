@@ -1,17 +1,24 @@
 use async_trait::async_trait;
-use jsonwebtoken::{errors::Error, Header, TokenData, Validation};
+use jsonwebtoken::{Header, TokenData, Validation};
 use serde::de::DeserializeOwned;
 
+use crate::utils::oauth::Error;
+
+mod introspection_decoder;
 mod jwks_decoder;
+mod jwks_provider;
+mod jwt_or_opaque;
 
 pub mod decoders {
+    pub use super::introspection_decoder::IntrospectionDecoder;
     pub use super::jwks_decoder::JwksDecoder;
+    pub use super::jwks_provider::JwksProvider;
+    pub use super::jwt_or_opaque::JwtOrOpaque;
 }
 
 ///
 /// JWT Decoder
 ///
-
 pub struct JwtDecoder<D>
 where
     D: Decoder,
@@ -24,15 +31,23 @@ impl<D> JwtDecoder<D>
 where
     D: Decoder,
 {
-    async fn decode<T>(&self, token: &str) -> Result<TokenData<T>, Error>
+    pub fn new(decoder: D, validation: Validation) -> Self {
+        Self {
+            validation,
+            decoder,
+        }
+    }
+
+    pub async fn decode<T>(&self, token: &str) -> Result<TokenData<T>, Error>
     where
         T: DeserializeOwned,
     {
-        let header = jsonwebtoken::decode_header(&token)?;
+        //== opaque tokens have no header
+        let header = jsonwebtoken::decode_header(token).ok();
 
         let info = DecodeInfo {
-            token: &token,
-            header: &header,
+            token,
+            header: header.as_ref(),
             validation: &self.validation,
         };
 
@@ -43,17 +58,15 @@ where
 ///
 /// Decode Info
 ///
-
 pub struct DecodeInfo<'a> {
     pub token: &'a str,
-    pub header: &'a Header,
+    pub header: Option<&'a Header>,
     pub validation: &'a Validation,
 }
 
 ///
 /// Decoder trait
 ///
-
 #[async_trait(?Send)]
 pub trait Decoder {
     async fn decode<T>(&self, dec_info: &DecodeInfo<'_>) -> Result<TokenData<T>, Error>
@@ -0,0 +1,76 @@
+use std::{future::Future, time::Instant};
+
+use futures::future::LocalBoxFuture;
+use mongodb::error::{Error, ErrorKind, WriteFailure};
+use tracing::{Instrument, Span};
+
+use crate::metrics;
+
+/// Mongo server error code raised when a unique index is violated
+pub const DUPLICATE_KEY_CODE: i32 = 11000;
+
+///
+/// Span of a single operation on `collection`, a child of the request span
+///
+pub fn span(collection: &str, operation: &'static str) -> Span {
+    tracing::info_span!(
+        "mongo",
+        db.system = "mongodb",
+        db.collection = collection,
+        db.operation = operation,
+    )
+}
+
+///
+/// Observe
+///
+/// Runs a mongo operation in its `span` and records its latency
+///
+pub trait Observe: Future + Sized {
+    fn observe<'a>(
+        self,
+        collection: &str,
+        operation: &'static str,
+    ) -> LocalBoxFuture<'a, Self::Output>
+    where
+        Self: 'a;
+}
+
+impl<F: Future + Sized> Observe for F {
+    fn observe<'a>(
+        self,
+        collection: &str,
+        operation: &'static str,
+    ) -> LocalBoxFuture<'a, Self::Output>
+    where
+        Self: 'a,
+    {
+        let histogram = metrics::MONGO_OPERATION_DURATION.with_label_values(&[collection, operation]);
+
+        Box::pin(
+            async move {
+                let start = Instant::now();
+                let output = self.await;
+                histogram.observe(start.elapsed().as_secs_f64());
+                output
+            }
+            .instrument(span(collection, operation)),
+        )
+    }
+}
+
+///
+/// Returns true if the mongo error was caused by a unique index violation
+///
+pub fn is_duplicate_key_error(error: &Error) -> bool {
+    match *error.kind {
+        ErrorKind::Write(WriteFailure::WriteError(ref e)) => e.code == DUPLICATE_KEY_CODE,
+        ErrorKind::Command(ref e) => e.code == DUPLICATE_KEY_CODE,
+        ErrorKind::BulkWrite(ref e) => e
+            .write_errors
+            .as_ref()
+            .map(|errs| errs.iter().any(|e| e.code == DUPLICATE_KEY_CODE))
+            .unwrap_or(false),
+        _ => false,
+    }
+}
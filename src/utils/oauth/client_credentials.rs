@@ -0,0 +1,352 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex as StdMutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use futures::lock::Mutex;
+use serde::Deserialize;
+
+use super::{http, Error, OpenIdConfig};
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct TokenKey {
+    audience: Option<String>,
+    scopes: BTreeSet<String>,
+}
+
+struct CachedToken {
+    access_token: String,
+    expires: Instant,
+}
+
+///
+/// ClientCredentials
+///
+/// Obtains access tokens for outbound calls with the OAuth2 client
+/// credentials grant. Tokens are cached per audience and scope set and
+/// refetched `refresh_before` their expiry. Share it as `web::Data`.
+///
+pub struct ClientCredentials {
+    token_endpoint: String,
+    client_id: String,
+    client_secret: String,
+    client: reqwest::Client,
+    refresh_before: Duration,
+    default_ttl: Duration,
+    cache: RwLock<HashMap<TokenKey, CachedToken>>,
+    refresh: StdMutex<HashMap<TokenKey, Arc<Mutex<()>>>>,
+}
+
+impl ClientCredentials {
+    pub fn new(
+        token_endpoint: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        Self {
+            token_endpoint: token_endpoint.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            client: http::client(),
+            refresh_before: Duration::from_secs(60),
+            default_ttl: Duration::from_secs(300),
+            cache: RwLock::new(HashMap::new()),
+            refresh: StdMutex::new(HashMap::new()),
+        }
+    }
+
+    ///
+    /// Builds a client for the config's `token_endpoint`, if it has one
+    ///
+    pub fn from_config(
+        config: &OpenIdConfig,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Option<Self> {
+        config
+            .token_endpoint
+            .as_ref()
+            .map(|endpoint| Self::new(endpoint, client_id, client_secret))
+    }
+
+    /// How long before expiry a cached token is replaced
+    pub fn refresh_before(mut self, refresh_before: Duration) -> Self {
+        self.refresh_before = refresh_before;
+        self
+    }
+
+    /// Lifetime assumed for tokens issued without `expires_in`
+    pub fn default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    ///
+    /// Returns an access token for `audience` with `scopes`
+    ///
+    pub async fn token(&self, audience: Option<&str>, scopes: &[&str]) -> Result<String, Error> {
+        let key = TokenKey {
+            audience: audience.map(String::from),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        };
+
+        if let Some(token) = self.cached(&key) {
+            return Ok(token);
+        }
+
+        let result = self.fetch_once(&key).await;
+
+        //== locks only held by the map have no waiters left
+        self.refresh
+            .lock()
+            .unwrap()
+            .retain(|_, lock| Arc::strong_count(lock) > 1);
+
+        result
+    }
+
+    ///
+    /// Returns an `Authorization` header value for `audience` with `scopes`
+    ///
+    pub async fn bearer(&self, audience: Option<&str>, scopes: &[&str]) -> Result<String, Error> {
+        Ok(format!("Bearer {}", self.token(audience, scopes).await?))
+    }
+
+    ///
+    /// Fetches and caches the token for `key`, single-flight per key
+    ///
+    async fn fetch_once(&self, key: &TokenKey) -> Result<String, Error> {
+        let lock = self.refresh_lock(key);
+        let _guard = lock.lock().await;
+
+        //== another caller may have fetched the token while waiting
+        if let Some(token) = self.cached(key) {
+            return Ok(token);
+        }
+
+        let token = self.fetch(key).await?;
+        let access_token = token.access_token.clone();
+
+        let mut cache = self.cache.write().unwrap();
+        cache.retain(|_, cached| cached.expires > Instant::now());
+        cache.insert(key.clone(), token);
+
+        Ok(access_token)
+    }
+
+    fn refresh_lock(&self, key: &TokenKey) -> Arc<Mutex<()>> {
+        self.refresh
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone()
+    }
+
+    fn cached(&self, key: &TokenKey) -> Option<String> {
+        let cache = self.cache.read().unwrap();
+
+        cache
+            .get(key)
+            .filter(|cached| cached.expires > Instant::now() + self.refresh_before)
+            .map(|cached| cached.access_token.clone())
+    }
+
+    async fn fetch(&self, key: &TokenKey) -> Result<CachedToken, Error> {
+        let scope = key
+            .scopes
+            .iter()
+            .cloned()
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        let mut form = vec![("grant_type", "client_credentials")];
+        if !scope.is_empty() {
+            form.push(("scope", &scope));
+        }
+        if let Some(ref audience) = key.audience {
+            form.push(("audience", audience));
+        }
+
+        let request = self
+            .client
+            .post(&self.token_endpoint)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&form);
+
+        let resp: TokenResponse = http::send_json(&self.token_endpoint, request).await?;
+        let ttl = resp
+            .expires_in
+            .map(Duration::from_secs)
+            .unwrap_or(self.default_ttl);
+
+        Ok(CachedToken {
+            access_token: resp.access_token,
+            expires: Instant::now() + ttl,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use futures::future::join_all;
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MockEndpoint {
+        calls: AtomicUsize,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    ///
+    /// Starts a mock token endpoint, tokens name their audience, scope and call
+    ///
+    /// Tokens for the `no-expiry` audience have no `expires_in`, all others
+    /// expire in 120 seconds. Each response is delayed to let callers overlap.
+    ///
+    fn mock_endpoint() -> (String, Arc<MockEndpoint>) {
+        let state = Arc::new(MockEndpoint::default());
+        let data = web::Data::from(state.clone());
+
+        let server = HttpServer::new(move || {
+            App::new().app_data(data.clone()).route(
+                    "/token",
+                    web::post().to(
+                        |form: web::Form<HashMap<String, String>>,
+                         state: web::Data<MockEndpoint>| async move {
+                            let call = state.calls.fetch_add(1, Ordering::SeqCst) + 1;
+                            let in_flight = state.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                            state.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+
+                            actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+                            state.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                            let audience = form.get("audience").cloned().unwrap_or_default();
+                            let scope = form.get("scope").cloned().unwrap_or_default();
+                            let access_token = format!("{}|{}|{}", audience, scope, call);
+
+                            if audience == "no-expiry" {
+                                HttpResponse::Ok().json(json!({ "access_token": access_token }))
+                            } else {
+                                HttpResponse::Ok().json(
+                                    json!({ "access_token": access_token, "expires_in": 120 }),
+                                )
+                            }
+                        },
+                    ),
+                )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+
+        let uri = format!("http://{}/token", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+
+        (uri, state)
+    }
+
+    #[actix_web::test]
+    async fn tokens_are_cached_per_audience_and_scope_set() {
+        let (uri, state) = mock_endpoint();
+        let client = ClientCredentials::new(uri, "client", "secret");
+
+        let token = client.token(Some("api"), &["b", "a"]).await.unwrap();
+        assert_eq!(token, "api|a b|1");
+
+        //== scope order does not matter
+        assert_eq!(client.token(Some("api"), &["a", "b"]).await.unwrap(), token);
+        assert_eq!(state.calls.load(Ordering::SeqCst), 1);
+
+        assert_eq!(client.token(Some("api"), &["a"]).await.unwrap(), "api|a|2");
+        assert_eq!(
+            client.token(Some("other"), &["a", "b"]).await.unwrap(),
+            "other|a b|3"
+        );
+        assert_eq!(client.token(None, &[]).await.unwrap(), "||4");
+        assert_eq!(state.calls.load(Ordering::SeqCst), 4);
+
+        assert_eq!(
+            client.bearer(Some("api"), &["a"]).await.unwrap(),
+            "Bearer api|a|2"
+        );
+        assert_eq!(state.calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[actix_web::test]
+    async fn tokens_are_refetched_within_refresh_before() {
+        let (uri, state) = mock_endpoint();
+
+        //== tokens live 120 seconds, less than refresh_before
+        let client = ClientCredentials::new(uri, "client", "secret")
+            .refresh_before(Duration::from_secs(180));
+
+        assert_eq!(client.token(Some("api"), &[]).await.unwrap(), "api||1");
+        assert_eq!(client.token(Some("api"), &[]).await.unwrap(), "api||2");
+        assert_eq!(state.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[actix_web::test]
+    async fn default_ttl_applies_without_expires_in() {
+        let (uri, state) = mock_endpoint();
+
+        let client = ClientCredentials::new(uri.clone(), "client", "secret")
+            .default_ttl(Duration::from_secs(3600));
+        let token = client.token(Some("no-expiry"), &[]).await.unwrap();
+        assert_eq!(client.token(Some("no-expiry"), &[]).await.unwrap(), token);
+        assert_eq!(state.calls.load(Ordering::SeqCst), 1);
+
+        //== a default shorter than refresh_before is never reused
+        let client =
+            ClientCredentials::new(uri, "client", "secret").default_ttl(Duration::from_secs(30));
+        client.token(Some("no-expiry"), &[]).await.unwrap();
+        client.token(Some("no-expiry"), &[]).await.unwrap();
+        assert_eq!(state.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[actix_web::test]
+    async fn concurrent_callers_share_one_fetch() {
+        let (uri, state) = mock_endpoint();
+        let client = ClientCredentials::new(uri, "client", "secret");
+
+        let tokens = join_all((0..8).map(|_| client.token(Some("api"), &["a"]))).await;
+
+        assert!(tokens
+            .iter()
+            .all(|token| matches!(token.as_deref(), Ok("api|a|1"))));
+        assert_eq!(state.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_web::test]
+    async fn different_keys_are_fetched_concurrently() {
+        let (uri, state) = mock_endpoint();
+        let client = ClientCredentials::new(uri, "client", "secret");
+
+        let tokens = join_all([
+            client.token(Some("api"), &[]),
+            client.token(Some("other"), &[]),
+        ])
+        .await;
+
+        assert!(tokens.iter().all(Result::is_ok));
+        assert_eq!(state.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(state.max_in_flight.load(Ordering::SeqCst), 2);
+        assert!(client.refresh.lock().unwrap().is_empty());
+    }
+}
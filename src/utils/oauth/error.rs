@@ -1 +1,121 @@
-pub enum Error {}
+use std::fmt;
+
+use actix_web::http::StatusCode;
+use jsonwebtoken::Algorithm;
+
+use crate::{auth, ErrorCode, RequestError};
+
+///
+/// OAuth Error
+///
+#[derive(Debug)]
+pub enum Error {
+    /// Request to the identity provider failed
+    Network { uri: String, source: reqwest::Error },
+    /// Identity provider answered with a non success status
+    HttpStatus { uri: String, status: u16 },
+    /// Identity provider document could not be parsed
+    MalformedDocument { uri: String, reason: String },
+    /// Identity provider could not be reached recently, no keys are cached
+    Unavailable { uri: String },
+    /// Discovered issuer differs from the configured one
+    IssuerMismatch { expected: String, found: String },
+    /// Token header has no `kid`
+    MissingKid,
+    /// No key matches the token's `kid`
+    UnknownKid(String),
+    /// Token algorithm is not supported by the decoder
+    UnsupportedAlg(Algorithm),
+    /// Key cannot verify the token (`alg`, `use` or key type mismatch)
+    InvalidKey(String),
+    /// Token failed to decode or validate
+    Jwt(jsonwebtoken::errors::Error),
+    /// Introspection reported the token as inactive
+    InactiveToken,
+}
+
+impl Error {
+    pub fn status_code(&self) -> StatusCode {
+        match *self {
+            Self::Network { .. }
+            | Self::HttpStatus { .. }
+            | Self::MalformedDocument { .. }
+            | Self::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            Self::IssuerMismatch { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::MissingKid
+            | Self::UnknownKid(_)
+            | Self::UnsupportedAlg(_)
+            | Self::InvalidKey(_)
+            | Self::Jwt(_)
+            | Self::InactiveToken => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Self::Network { ref source, .. } => Some(source),
+            Self::Jwt(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Network {
+                ref uri,
+                ref source,
+            } => write!(f, "request to {} failed: {}", uri, source),
+            Self::HttpStatus { ref uri, status } => {
+                write!(f, "request to {} returned status {}", uri, status)
+            }
+            Self::MalformedDocument {
+                ref uri,
+                ref reason,
+            } => write!(f, "malformed document from {}: {}", uri, reason),
+            Self::Unavailable { ref uri } => write!(f, "keys unavailable from {}", uri),
+            Self::IssuerMismatch {
+                ref expected,
+                ref found,
+            } => write!(f, "issuer mismatch: expected {}, found {}", expected, found),
+            Self::MissingKid => write!(f, "token header has no kid"),
+            Self::UnknownKid(ref kid) => write!(f, "no key found for kid {}", kid),
+            Self::UnsupportedAlg(ref alg) => write!(f, "unsupported algorithm {:?}", alg),
+            Self::InvalidKey(ref reason) => write!(f, "invalid key: {}", reason),
+            Self::Jwt(ref e) => write!(f, "{}", e),
+            Self::InactiveToken => write!(f, "token is not active"),
+        }
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for Error {
+    fn from(error: jsonwebtoken::errors::Error) -> Self {
+        Self::Jwt(error)
+    }
+}
+
+impl From<Error> for RequestError {
+    fn from(error: Error) -> Self {
+        let code = error.status_code();
+
+        if code == StatusCode::UNAUTHORIZED {
+            return auth::errs::invalid_token(&error.to_string());
+        }
+
+        let error_code = if code == StatusCode::SERVICE_UNAVAILABLE {
+            ErrorCode::ServiceUnavailable
+        } else {
+            ErrorCode::InternalServerError
+        };
+
+        RequestError::builder()
+            .code(code)
+            .error(error_code)
+            .message("Unable to verify credentials")
+            .source(Some(error.to_string().into()))
+            .build()
+    }
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use reqwest::RequestBuilder;
+use serde::de::DeserializeOwned;
+
+use super::Error;
+use crate::web::{RequestId, X_REQUEST_ID};
+
+/// Time allowed to establish a connection to an issuer
+pub(crate) const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Time allowed for a whole outbound call, so a hung issuer can't stall callers
+pub(crate) const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("http client configuration is valid");
+}
+
+///
+/// Http client for outbound oauth calls, bounded by the timeouts above.
+/// Clones share one connection pool.
+///
+pub(crate) fn client() -> reqwest::Client {
+    CLIENT.clone()
+}
+
+///
+/// GETs `uri` and parses the json body, mapping failures into oauth errors
+///
+pub(crate) async fn get_json<T: DeserializeOwned>(uri: &str) -> Result<T, Error> {
+    send_json(uri, client().get(uri)).await
+}
+
+///
+/// Sends `request` to `uri` and parses the json body, mapping failures into oauth errors
+///
+pub(crate) async fn send_json<T: DeserializeOwned>(
+    uri: &str,
+    request: RequestBuilder,
+) -> Result<T, Error> {
+    //== correlate outbound calls with the request that made them
+    let request = match RequestId::current() {
+        Some(id) => request.header(X_REQUEST_ID, id.0),
+        None => request,
+    };
+
+    let resp = request.send().await.map_err(|source| Error::Network {
+        uri: uri.to_string(),
+        source,
+    })?;
+
+    if !resp.status().is_success() {
+        return Err(Error::HttpStatus {
+            uri: uri.to_string(),
+            status: resp.status().as_u16(),
+        });
+    }
+
+    let body = resp.bytes().await.map_err(|source| Error::Network {
+        uri: uri.to_string(),
+        source,
+    })?;
+
+    serde_json::from_slice(&body).map_err(|e| Error::MalformedDocument {
+        uri: uri.to_string(),
+        reason: e.to_string(),
+    })
+}
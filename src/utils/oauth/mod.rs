@@ -1,4 +1,11 @@
+mod client_credentials;
+mod error;
+pub(crate) mod http;
+mod oidc_provider;
 mod openid_config;
+pub use client_credentials::ClientCredentials;
+pub use error::Error;
+pub use oidc_provider::OidcProvider;
 pub use openid_config::OpenIdConfig;
 
 pub mod models;
@@ -1,29 +1,54 @@
-use reqwest;
 use serde::{Deserialize, Serialize};
 
+use super::{http, Error};
+
 #[derive(Serialize, Deserialize)]
 pub struct Jwks {
     pub keys: Vec<Jwk>,
 }
 
 impl Jwks {
-    pub async fn from_uri(uri: impl AsRef<str>) -> Result<Self, String> {
-        let resp = reqwest::get(uri.as_ref())
-            .await
-            .map_err(|e| e.to_string())?;
-        resp.json().await.map_err(|e| e.to_string())
+    pub async fn from_uri(uri: impl AsRef<str>) -> Result<Self, Error> {
+        http::get_json(uri.as_ref()).await
     }
 
     pub fn jwk(&self, kid: impl AsRef<str>) -> Option<&Jwk> {
-        self.keys.iter().find(|k| k.kid == kid.as_ref())
+        self.keys
+            .iter()
+            .find(|k| k.kid.as_deref() == Some(kid.as_ref()))
     }
 }
 
+///
+/// Json Web Key
+///
 #[derive(Serialize, Deserialize)]
 pub struct Jwk {
-    pub kty: String,
-    pub alg: String,
-    pub kid: String,
-    pub e: String,
-    pub n: String,
+    pub kid: Option<String>,
+    pub alg: Option<String>,
+
+    #[serde(rename = "use")]
+    pub key_use: Option<String>,
+
+    #[serde(flatten)]
+    pub key: JwkKey,
+}
+
+///
+/// Key material by key type (`kty`)
+///
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kty")]
+pub enum JwkKey {
+    #[serde(rename = "RSA")]
+    Rsa { n: String, e: String },
+
+    #[serde(rename = "EC")]
+    Ec { crv: String, x: String, y: String },
+
+    #[serde(rename = "OKP")]
+    Okp { crv: String, x: String },
+
+    #[serde(other)]
+    Unsupported,
 }
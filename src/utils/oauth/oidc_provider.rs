@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use jsonwebtoken::{Algorithm, Validation};
+
+use super::{ClientCredentials, Error, OpenIdConfig};
+use crate::utils::jwt::{
+    decoders::{IntrospectionDecoder, JwksDecoder, JwksProvider},
+    JwtDecoder,
+};
+
+///
+/// OidcProvider
+///
+/// Discovers an issuer's configuration and builds decoders preconfigured
+/// with its issuer, keys and signing algorithms.
+///
+pub struct OidcProvider {
+    config: OpenIdConfig,
+    audience: Vec<String>,
+    jwks: Arc<JwksProvider>,
+}
+
+impl OidcProvider {
+    pub async fn discover(issuer: impl AsRef<str>) -> Result<Self, Error> {
+        let issuer = issuer.as_ref().trim_end_matches('/');
+        let uri = format!("{}/.well-known/openid-configuration", issuer);
+
+        let config = OpenIdConfig::from_well_known(uri).await?;
+
+        //== issuer in the document must match the one it was fetched from
+        if config.issuer.trim_end_matches('/') != issuer {
+            return Err(Error::IssuerMismatch {
+                expected: issuer.to_string(),
+                found: config.issuer,
+            });
+        }
+
+        Ok(Self {
+            jwks: Arc::new(JwksProvider::default(&config.jwks_uri)),
+            config,
+            audience: vec![],
+        })
+    }
+
+    /// Accepted `aud` values, unchecked when empty
+    pub fn audience<T: ToString>(mut self, audience: &[T]) -> Self {
+        self.audience = audience.iter().map(ToString::to_string).collect();
+        self
+    }
+
+    pub fn config(&self) -> &OpenIdConfig {
+        &self.config
+    }
+
+    ///
+    /// Asymmetric algorithms advertised by the issuer, RS256 if none are
+    ///
+    pub fn algorithms(&self) -> Vec<Algorithm> {
+        let algorithms: Vec<Algorithm> = self
+            .config
+            .id_token_signing_alg_values_supported
+            .iter()
+            .filter_map(|alg| alg.parse::<Algorithm>().ok())
+            .filter(|alg| !matches!(alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512))
+            .collect();
+
+        if algorithms.is_empty() {
+            vec![Algorithm::RS256]
+        } else {
+            algorithms
+        }
+    }
+
+    pub fn validation(&self) -> Validation {
+        let algorithms = self.algorithms();
+
+        let mut validation = Validation::new(algorithms[0]);
+        validation.algorithms = algorithms;
+        validation.set_issuer(&[&self.config.issuer]);
+
+        if !self.audience.is_empty() {
+            validation.set_audience(&self.audience);
+        }
+
+        validation
+    }
+
+    ///
+    /// Key set cache of the issuer, shared by every decoder built here
+    ///
+    pub fn jwks_provider(&self) -> Arc<JwksProvider> {
+        self.jwks.clone()
+    }
+
+    pub fn jwks_decoder(&self) -> JwksDecoder {
+        JwksDecoder::new(self.jwks.clone())
+    }
+
+    pub fn jwt_decoder(&self) -> JwtDecoder<JwksDecoder> {
+        JwtDecoder::new(self.jwks_decoder(), self.validation())
+    }
+
+    ///
+    /// Client credentials grant client, if the issuer has a token endpoint
+    ///
+    pub fn client_credentials(
+        &self,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Option<ClientCredentials> {
+        ClientCredentials::from_config(&self.config, client_id, client_secret)
+    }
+
+    ///
+    /// Decoder for opaque tokens, if the issuer has an introspection endpoint
+    ///
+    pub fn introspection_decoder(
+        &self,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Option<IntrospectionDecoder> {
+        self.config
+            .introspection_endpoint
+            .as_ref()
+            .map(|endpoint| IntrospectionDecoder::new(endpoint, client_id, client_secret))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use serde_json::json;
+
+    use super::*;
+
+    ///
+    /// Starts a mock issuer serving a discovery document per tenant path
+    ///
+    /// `/good` describes itself, `/spoofed` claims to be `/good`.
+    ///
+    fn mock_issuer() -> String {
+        let server = HttpServer::new(|| {
+            App::new().route(
+                "/{tenant}/.well-known/openid-configuration",
+                web::get().to(|req: HttpRequest, tenant: web::Path<String>| async move {
+                    let base = format!("http://{}", req.connection_info().host());
+                    let issuer = match tenant.as_str() {
+                        "spoofed" => format!("{}/good", base),
+                        tenant => format!("{}/{}", base, tenant),
+                    };
+
+                    HttpResponse::Ok().json(json!({
+                        "issuer": issuer,
+                        "jwks_uri": format!("{}/jwks.json", issuer),
+                        "id_token_signing_alg_values_supported": ["ES256", "HS256"],
+                    }))
+                }),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+
+        let uri = format!("http://{}", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+
+        uri
+    }
+
+    #[actix_web::test]
+    async fn discovers_matching_issuer() {
+        let base = mock_issuer();
+
+        let provider = OidcProvider::discover(format!("{}/good/", base))
+            .await
+            .unwrap();
+        assert_eq!(provider.config().issuer, format!("{}/good", base));
+        assert_eq!(provider.algorithms(), vec![Algorithm::ES256]);
+    }
+
+    #[actix_web::test]
+    async fn mismatched_issuer_is_rejected() {
+        let base = mock_issuer();
+
+        match OidcProvider::discover(format!("{}/spoofed", base)).await {
+            Err(Error::IssuerMismatch { expected, found }) => {
+                assert_eq!(expected, format!("{}/spoofed", base));
+                assert_eq!(found, format!("{}/good", base));
+            }
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("spoofed issuer was accepted"),
+        }
+    }
+}
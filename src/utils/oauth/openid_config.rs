@@ -1,18 +1,93 @@
-use reqwest;
 use serde::Deserialize;
+use serde_json::{Map, Value};
 
+use super::{http, Error};
+
+///
+/// OpenID Connect Discovery document
+///
+/// See https://openid.net/specs/openid-connect-discovery-1_0.html#ProviderMetadata
+///
 #[derive(Deserialize)]
 pub struct OpenIdConfig {
     pub issuer: String,
-    pub authorization_endpoint: String,
-    pub token_endpoint: String,
-    pub request_object_signing_alg_values_supported: Vec<String>,
     pub jwks_uri: String,
+    pub authorization_endpoint: Option<String>,
+    pub token_endpoint: Option<String>,
+    pub userinfo_endpoint: Option<String>,
+    pub registration_endpoint: Option<String>,
+    pub introspection_endpoint: Option<String>,
+    pub revocation_endpoint: Option<String>,
+    pub end_session_endpoint: Option<String>,
+    pub check_session_iframe: Option<String>,
+
+    #[serde(default)]
+    pub scopes_supported: Vec<String>,
+    #[serde(default)]
+    pub response_types_supported: Vec<String>,
+    #[serde(default)]
+    pub response_modes_supported: Vec<String>,
+    #[serde(default)]
+    pub grant_types_supported: Vec<String>,
+    #[serde(default)]
+    pub acr_values_supported: Vec<String>,
+    #[serde(default)]
+    pub subject_types_supported: Vec<String>,
+
+    #[serde(default)]
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    #[serde(default)]
+    pub id_token_encryption_alg_values_supported: Vec<String>,
+    #[serde(default)]
+    pub id_token_encryption_enc_values_supported: Vec<String>,
+    #[serde(default)]
+    pub userinfo_signing_alg_values_supported: Vec<String>,
+    #[serde(default)]
+    pub userinfo_encryption_alg_values_supported: Vec<String>,
+    #[serde(default)]
+    pub userinfo_encryption_enc_values_supported: Vec<String>,
+    #[serde(default)]
+    pub request_object_signing_alg_values_supported: Vec<String>,
+    #[serde(default)]
+    pub request_object_encryption_alg_values_supported: Vec<String>,
+    #[serde(default)]
+    pub request_object_encryption_enc_values_supported: Vec<String>,
+    #[serde(default)]
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    #[serde(default)]
+    pub token_endpoint_auth_signing_alg_values_supported: Vec<String>,
+    #[serde(default)]
+    pub introspection_endpoint_auth_methods_supported: Vec<String>,
+
+    #[serde(default)]
+    pub display_values_supported: Vec<String>,
+    #[serde(default)]
+    pub claim_types_supported: Vec<String>,
+    #[serde(default)]
+    pub claims_supported: Vec<String>,
+    #[serde(default)]
+    pub claims_locales_supported: Vec<String>,
+    #[serde(default)]
+    pub ui_locales_supported: Vec<String>,
+    #[serde(default)]
+    pub code_challenge_methods_supported: Vec<String>,
+
+    pub claims_parameter_supported: Option<bool>,
+    pub request_parameter_supported: Option<bool>,
+    pub request_uri_parameter_supported: Option<bool>,
+    pub require_request_uri_registration: Option<bool>,
+
+    pub service_documentation: Option<String>,
+    pub op_policy_uri: Option<String>,
+    pub op_tos_uri: Option<String>,
+
+    /// Provider specific metadata
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 impl OpenIdConfig {
-    pub async fn from_well_known(uri: impl Into<String>) -> Result<Self, String> {
-        let resp = reqwest::get(uri.into()).await.map_err(|e| e.to_string())?;
-        resp.json().await.map_err(|e| e.to_string())
+    pub async fn from_well_known(uri: impl Into<String>) -> Result<Self, Error> {
+        http::get_json(&uri.into()).await
     }
 }
@@ -4,7 +4,9 @@ use validator::ValidationError;
 
 pub fn validate_iso_8601(value: &str) -> Result<(), ValidationError> {
     lazy_static! {
-        static ref RE: Regex = Regex::new(r"^([\+-]?\d{4}(?!\d{2}\b))((-?)((0[1-9]|1[0-2])(\3([12]\d|0[1-9]|3[01]))?|W([0-4]\d|5[0-2])(-?[1-7])?|(00[1-9]|0[1-9]\d|[12]\d{2}|3([0-5]\d|6[1-6])))([T\s]((([01]\d|2[0-3])((:?)[0-5]\d)?|24\:?00)([\.,]\d+(?!:))?)?(\17[0-5]\d([\.,]\d+)?)?([zZ]|([\+-])([01]\d|2[0-3]):?([0-5]\d)?)?)?)?$").unwrap();
+        //== calendar, week or ordinal dates with an optional time and offset,
+        //== without look-around or back-references which `regex` lacks
+        static ref RE: Regex = Regex::new(r"^[\+-]?\d{4}(-(0[1-9]|1[0-2])(-(0[1-9]|[12]\d|3[01]))?|(0[1-9]|1[0-2])(0[1-9]|[12]\d|3[01])|-?W([0-4]\d|5[0-2])(-?[1-7])?|-?(00[1-9]|0[1-9]\d|[12]\d{2}|3([0-5]\d|6[1-6])))?([T\s](([01]\d|2[0-3])(:?[0-5]\d(:?[0-5]\d([\.,]\d+)?)?)?|24:?00)([zZ]|[\+-]([01]\d|2[0-3])(:?[0-5]\d)?)?)?$").unwrap();
     }
 
     if RE.is_match(value) {
@@ -25,3 +27,47 @@ pub fn validate_alpha_numeric(value: &str) -> Result<(), ValidationError> {
         Err(ValidationError::new("INVALID_ALPHA_NUMERIC"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iso_8601_dates_are_valid() {
+        for value in [
+            "2022",
+            "2022-08",
+            "2022-08-09",
+            "20220809",
+            "2022-W32-2",
+            "2022-221",
+            "2022-08-09T10:00:00Z",
+            "2022-08-09T10:00:00.123+02:00",
+            "2022-08-09 10:00",
+            "20220809T100000Z",
+        ] {
+            assert!(validate_iso_8601(value).is_ok(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn non_iso_8601_values_are_invalid() {
+        for value in [
+            "",
+            "yesterday",
+            "2022-13-01",
+            "2022-08-32",
+            "2022-08-09T25:00",
+            "09/08/2022",
+        ] {
+            assert!(validate_iso_8601(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn alpha_numeric_rejects_other_characters() {
+        assert!(validate_alpha_numeric("Ada1").is_ok());
+        assert!(validate_alpha_numeric("Ada Lovelace").is_err());
+        assert!(validate_alpha_numeric("").is_err());
+    }
+}
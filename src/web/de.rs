@@ -0,0 +1,187 @@
+use serde::de::{self, value::Error, IntoDeserializer, Visitor};
+
+///
+/// TextDeserializer
+///
+/// Deserializes a single textual request value (path segment, query
+/// param) parsing it into whatever primitive the target type asks for.
+///
+pub(crate) struct TextDeserializer<'a> {
+    value: &'a str,
+}
+
+impl<'a> TextDeserializer<'a> {
+    pub fn new(value: &'a str) -> Self {
+        Self { value }
+    }
+
+    fn parse<T: std::str::FromStr>(&self, expected: &str) -> Result<T, Error> {
+        self.value.parse().map_err(|_| {
+            de::Error::custom(format!(
+                "invalid value `{}`, expected {}",
+                self.value, expected
+            ))
+        })
+    }
+}
+
+impl<'a> IntoDeserializer<'a, Error> for TextDeserializer<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident, $expected:expr;)+) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.$visit(self.parse($expected)?)
+            }
+        )+
+    };
+}
+
+impl<'de> de::Deserializer<'de> for TextDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_borrowed_str(self.value)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool, "a boolean";
+        deserialize_i8 => visit_i8, "an integer";
+        deserialize_i16 => visit_i16, "an integer";
+        deserialize_i32 => visit_i32, "an integer";
+        deserialize_i64 => visit_i64, "an integer";
+        deserialize_u8 => visit_u8, "a positive integer";
+        deserialize_u16 => visit_u16, "a positive integer";
+        deserialize_u32 => visit_u32, "a positive integer";
+        deserialize_u64 => visit_u64, "a positive integer";
+        deserialize_f32 => visit_f32, "a number";
+        deserialize_f64 => visit_f64, "a number";
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_enum(self.value.into_deserializer(), name, variants, visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+///
+/// ValuesDeserializer
+///
+/// Deserializes the values of a repeated request param, a sequence when
+/// the target asks for one, otherwise the param's single value.
+///
+pub(crate) struct ValuesDeserializer<'a> {
+    values: &'a [String],
+}
+
+impl<'a> ValuesDeserializer<'a> {
+    pub fn new(values: &'a [String]) -> Self {
+        Self { values }
+    }
+
+    fn single(&self) -> Result<TextDeserializer<'a>, Error> {
+        match self.values {
+            [value] => Ok(TextDeserializer::new(value)),
+            _ => Err(de::Error::custom(format!(
+                "expected a single value, found {}",
+                self.values.len()
+            ))),
+        }
+    }
+
+    fn seq(&self) -> de::value::SeqDeserializer<impl Iterator<Item = TextDeserializer<'a>>, Error> {
+        de::value::SeqDeserializer::new(self.values.iter().map(|v| TextDeserializer::new(v)))
+    }
+}
+
+macro_rules! deserialize_single {
+    ($($method:ident)+) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                self.single()?.$method(visitor)
+            }
+        )+
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValuesDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.values {
+            [value] => visitor.visit_borrowed_str(value),
+            _ => visitor.visit_seq(self.seq()),
+        }
+    }
+
+    deserialize_single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_identifier
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(self.seq())
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(self.seq())
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 bytes byte_buf unit unit_struct tuple_struct map struct
+    }
+}
@@ -0,0 +1,272 @@
+use std::{fmt, rc::Rc};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{HeaderMap, ACCEPT},
+        StatusCode,
+    },
+    Error, HttpResponse, ResponseError,
+};
+use futures::future::{ok, LocalBoxFuture, Ready};
+
+use crate::{
+    error::{DEFAULT_PROBLEM_TYPE_BASE, PROBLEM_JSON},
+    ErrorFormat, RequestError,
+};
+
+///
+/// ErrorResponses Middleware
+///
+/// Renders `RequestError` responses in the configured `ErrorFormat`.
+/// Clients listing `application/problem+json` in `Accept` always
+/// receive problem details, with the request path as `instance`.
+///
+pub struct ErrorResponses {
+    format: ErrorFormat,
+    type_base: Rc<String>,
+}
+
+impl ErrorResponses {
+    pub fn new(format: ErrorFormat) -> Self {
+        Self {
+            format,
+            type_base: Rc::new(DEFAULT_PROBLEM_TYPE_BASE.into()),
+        }
+    }
+
+    /// Prefix of problem `type` URIs, e.g. `https://example.com/problems/`
+    pub fn type_base(mut self, type_base: impl Into<String>) -> Self {
+        self.type_base = Rc::new(type_base.into());
+        self
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ErrorResponses
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ErrorResponsesMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ErrorResponsesMiddleware {
+            service: Rc::new(service),
+            format: self.format,
+            type_base: self.type_base.clone(),
+        })
+    }
+}
+
+pub struct ErrorResponsesMiddleware<S> {
+    service: Rc<S>,
+    format: ErrorFormat,
+    type_base: Rc<String>,
+}
+
+impl<S, B> Service<ServiceRequest> for ErrorResponsesMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let type_base = self.type_base.clone();
+
+        let format = if accepts_problem(req.headers()) {
+            ErrorFormat::Problem
+        } else {
+            self.format
+        };
+
+        //== the legacy format is what RequestError renders by default
+        if format == ErrorFormat::Legacy {
+            return Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) });
+        }
+
+        let instance = req.path().to_string();
+
+        Box::pin(async move {
+            match service.call(req).await {
+                Ok(res) => {
+                    let resp = res
+                        .response()
+                        .error()
+                        .and_then(|e| e.as_error::<RequestError>())
+                        .map(|e| e.response(format, &type_base, Some(&instance)));
+
+                    Ok(match resp {
+                        Some(resp) => res.into_response(resp).map_into_right_body(),
+                        None => res.map_into_left_body(),
+                    })
+                }
+                //== errors raised by inner middleware are rendered later by actix
+                Err(e) => match e.as_error::<RequestError>() {
+                    Some(error) => Err(Negotiated {
+                        error: error.clone(),
+                        format,
+                        type_base: type_base.to_string(),
+                        instance,
+                    }
+                    .into()),
+                    None => Err(e),
+                },
+            }
+        })
+    }
+}
+
+///
+/// A `RequestError` to be rendered in a negotiated format
+///
+#[derive(Debug)]
+struct Negotiated {
+    error: RequestError,
+    format: ErrorFormat,
+    type_base: String,
+    instance: String,
+}
+
+impl fmt::Display for Negotiated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl ResponseError for Negotiated {
+    fn status_code(&self) -> StatusCode {
+        self.error.code
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.error.report();
+        self.error
+            .response(self.format, &self.type_base, Some(&self.instance))
+    }
+}
+
+///
+/// True if the `Accept` header lists problem+json with a non zero quality
+///
+fn accepts_problem(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|item| {
+            let mut params = item.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default();
+
+            //== `q=0` explicitly refuses the media type
+            let quality = params
+                .find_map(|param| param.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            media_type.eq_ignore_ascii_case(PROBLEM_JSON) && quality > 0.0
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::header::{HeaderValue, CONTENT_TYPE},
+        test::{call_service, init_service, read_body_json, TestRequest},
+        web, App,
+    };
+    use serde_json::Value;
+
+    use super::*;
+
+    fn accepts(accept: &str) -> bool {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_str(accept).unwrap());
+        accepts_problem(&headers)
+    }
+
+    async fn not_found() -> Result<HttpResponse, RequestError> {
+        Err(RequestError::builder()
+            .code(StatusCode::NOT_FOUND)
+            .message("User not found")
+            .build())
+    }
+
+    async fn get(format: ErrorFormat, accept: &str) -> (String, Value) {
+        let app = init_service(
+            App::new()
+                .wrap(ErrorResponses::new(format))
+                .route("/users/42", web::get().to(not_found)),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/users/42")
+            .insert_header((ACCEPT, accept))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let content_type = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        (content_type, read_body_json(resp).await)
+    }
+
+    #[test]
+    fn accepts_listed_problem_json() {
+        assert!(accepts(PROBLEM_JSON));
+        assert!(accepts("application/json, application/problem+json;q=0.5"));
+        assert!(accepts("Application/Problem+JSON"));
+    }
+
+    #[test]
+    fn refused_or_unlisted_problem_json_is_not_accepted() {
+        assert!(!accepts("application/problem+json;q=0"));
+        assert!(!accepts("*/*"));
+        assert!(!accepts("application/json"));
+        assert!(!accepts_problem(&HeaderMap::new()));
+    }
+
+    #[actix_web::test]
+    async fn problem_json_accept_gets_problem_details() {
+        let (content_type, body) = get(ErrorFormat::Legacy, PROBLEM_JSON).await;
+        assert_eq!(content_type, PROBLEM_JSON);
+        assert_eq!(body["type"], "/problems/resource-not-found");
+        assert_eq!(body["status"], 404);
+        assert_eq!(body["instance"], "/users/42");
+    }
+
+    #[actix_web::test]
+    async fn wildcard_accept_gets_configured_format() {
+        let (content_type, body) = get(ErrorFormat::Legacy, "*/*").await;
+        assert_eq!(content_type, "application/json");
+        assert_eq!(body["error"], "RESOURCE_NOT_FOUND");
+
+        let (content_type, body) = get(ErrorFormat::Problem, "*/*").await;
+        assert_eq!(content_type, PROBLEM_JSON);
+        assert_eq!(body["code"], "RESOURCE_NOT_FOUND");
+    }
+
+    #[actix_web::test]
+    async fn json_accept_falls_back_to_legacy() {
+        let (content_type, body) = get(ErrorFormat::Legacy, "application/json").await;
+        assert_eq!(content_type, "application/json");
+        assert_eq!(body["code"], "404");
+        assert_eq!(body["message"], "User not found");
+    }
+}
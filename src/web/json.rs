@@ -1,38 +1,43 @@
 use std::{
-    error::Error,
-    ops::{self, Deref},
+    future::Future,
+    ops,
+    pin::Pin,
     sync::Arc,
+    task::{Context, Poll},
 };
 
-use actix_web::web::Json as ActixJson;
-use actix_web::FromRequest;
-use actix_web::{dev::JsonBody, error::JsonPayloadError};
-use actix_web::{http::StatusCode, Error, HttpRequest};
-
-use futures::future::{err, ok, Ready};
-use serde::{
-    de::{self, DeserializeOwned},
-    Serialize,
+use actix_web::{
+    dev::{JsonBody, Payload},
+    error::JsonPayloadError,
+    http::StatusCode,
+    web, FromRequest, HttpRequest, ResponseError,
 };
-use serde_json;
+use serde::{de::DeserializeOwned, Serialize};
 use validator::Validate;
 
 use crate::{error::RequestError, ErrorCode};
 
-type JsonErrorHandler = Option<Arc<dyn Fn(JsonPayloadError, &HttpRequest) -> dyn Error + Send + Sync>>;
+type JsonErrorHandler =
+    Option<Arc<dyn Fn(JsonPayloadError, &HttpRequest) -> RequestError + Send + Sync>>;
 
-pub struct JsonExtractFut<T> {
-    req: Option<HttpRequest>,
-    fut: JsonBody<T>,
-    err_handler: JsonErrorHandler,
-}
+type JsonContentTypeFn = Option<Arc<dyn Fn(mime::Mime) -> bool + Send + Sync>>;
 
+///
+/// Json
+///
+/// Json body extractor that runs `Validate` on the deserialized value.
+/// Validation failures become `VALIDATION_ERROR` responses, body errors
+/// go through the `JsonConfig` error handler.
+///
 pub struct Json<T>(pub T);
 
-impl<T> ops::Deref for Json<T>
-where
-    T: Validate,
-{
+impl<T> Json<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> ops::Deref for Json<T> {
     type Target = T;
 
     fn deref(&self) -> &T {
@@ -40,6 +45,12 @@ where
     }
 }
 
+impl<T> ops::DerefMut for Json<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
 impl<T: Serialize> Serialize for Json<T> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -72,3 +83,244 @@ where
         }
     }
 }
+
+pub struct JsonExtractFut<T> {
+    req: Option<HttpRequest>,
+    fut: JsonBody<T>,
+    err_handler: JsonErrorHandler,
+}
+
+impl<T> Future for JsonExtractFut<T>
+where
+    T: DeserializeOwned + Validate,
+{
+    type Output = Result<Json<T>, RequestError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        let res = match Pin::new(&mut this.fut).poll(cx) {
+            Poll::Ready(res) => res,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        Poll::Ready(match res {
+            Ok(data) => data
+                .validate()
+                .map(|_| Json(data))
+                .map_err(RequestError::from),
+            Err(e) => {
+                let req = this.req.take().unwrap();
+
+                Err(match this.err_handler.as_ref() {
+                    Some(err_handler) => (*err_handler)(e, &req),
+                    None => invalid_body(e),
+                })
+            }
+        })
+    }
+}
+
+///
+/// Default handler, keeps the payload error's status (400 or 413) and
+/// answers wrong or missing content types with 415
+///
+fn invalid_body(error: JsonPayloadError) -> RequestError {
+    let code = match error {
+        JsonPayloadError::ContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        _ => error.status_code(),
+    };
+
+    RequestError::builder()
+        .code(code)
+        .error(ErrorCode::InvalidBody)
+        .message("Invalid Json Content")
+        .detail(Some(error.to_string().into()))
+        .build()
+}
+
+///
+/// JsonConfig
+///
+/// Limit, accepted content types and error handler of `Json<T>`,
+/// registered with `App::app_data`
+///
+#[derive(Clone)]
+pub struct JsonConfig {
+    limit: usize,
+    err_handler: JsonErrorHandler,
+    content_type: JsonContentTypeFn,
+    content_type_required: bool,
+}
+
+impl JsonConfig {
+    /// Maximum payload size in bytes
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn error_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(JsonPayloadError, &HttpRequest) -> RequestError + Send + Sync + 'static,
+    {
+        self.err_handler = Some(Arc::new(f));
+        self
+    }
+
+    /// Accepts content types other than `application/json`
+    pub fn content_type<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(mime::Mime) -> bool + Send + Sync + 'static,
+    {
+        self.content_type = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Rejects requests without a content type
+    pub fn content_type_required(mut self, content_type_required: bool) -> Self {
+        self.content_type_required = content_type_required;
+        self
+    }
+
+    fn from_req(req: &HttpRequest) -> &Self {
+        req.app_data::<Self>()
+            .or_else(|| req.app_data::<web::Data<Self>>().map(|d| d.as_ref()))
+            .unwrap_or(&DEFAULT_CONFIG)
+    }
+}
+
+const DEFAULT_LIMIT: usize = 2_097_152; // 2 mb
+
+const DEFAULT_CONFIG: JsonConfig = JsonConfig {
+    limit: DEFAULT_LIMIT,
+    err_handler: None,
+    content_type: None,
+    content_type_required: true,
+};
+
+impl Default for JsonConfig {
+    fn default() -> Self {
+        DEFAULT_CONFIG
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::header::CONTENT_TYPE,
+        test::{call_service, init_service, read_body_json, TestRequest},
+        App, HttpResponse,
+    };
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    #[derive(Deserialize, Validate)]
+    struct Body {
+        #[validate(length(min = 3))]
+        name: String,
+    }
+
+    async fn create(body: Json<Body>) -> HttpResponse {
+        HttpResponse::Ok().json(json!({ "name": body.name }))
+    }
+
+    async fn post(config: JsonConfig, req: TestRequest) -> (StatusCode, Value) {
+        let app = init_service(
+            App::new()
+                .app_data(config)
+                .route("/", web::post().to(create)),
+        )
+        .await;
+
+        let resp = call_service(&app, req.uri("/").to_request()).await;
+        (resp.status(), read_body_json(resp).await)
+    }
+
+    fn json_body(body: &str) -> TestRequest {
+        TestRequest::post()
+            .insert_header((CONTENT_TYPE, "application/json"))
+            .set_payload(body.to_string())
+    }
+
+    #[actix_web::test]
+    async fn valid_body_is_extracted() {
+        let (status, body) = post(JsonConfig::default(), json_body(r#"{"name":"alice"}"#)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "name": "alice" }));
+    }
+
+    #[actix_web::test]
+    async fn validation_failure_is_validation_error() {
+        let (status, body) = post(JsonConfig::default(), json_body(r#"{"name":"al"}"#)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "VALIDATION_ERROR");
+        assert!(body["detail"]["name"].is_array());
+    }
+
+    #[actix_web::test]
+    async fn malformed_body_is_invalid_body() {
+        let (status, body) = post(JsonConfig::default(), json_body(r#"{"name":"#)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "INVALID_BODY");
+    }
+
+    #[actix_web::test]
+    async fn oversize_body_exceeds_limit() {
+        let config = JsonConfig::default().limit(8);
+        let (status, body) = post(config, json_body(r#"{"name":"alice"}"#)).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["error"], "INVALID_BODY");
+        assert!(body["detail"].as_str().unwrap().contains("limit: 8"));
+    }
+
+    #[actix_web::test]
+    async fn wrong_content_type_is_unsupported() {
+        let req = TestRequest::post()
+            .insert_header((CONTENT_TYPE, "text/plain"))
+            .set_payload(r#"{"name":"alice"}"#);
+        let (status, body) = post(JsonConfig::default(), req).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["error"], "INVALID_BODY");
+    }
+
+    #[actix_web::test]
+    async fn accepted_content_types_are_configurable() {
+        let config = JsonConfig::default().content_type(|mime| mime.subtype() == "plain");
+        let req = TestRequest::post()
+            .insert_header((CONTENT_TYPE, "text/plain"))
+            .set_payload(r#"{"name":"alice"}"#);
+        let (status, _) = post(config, req).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn missing_content_type_follows_content_type_required() {
+        let req = || TestRequest::post().set_payload(r#"{"name":"alice"}"#);
+
+        let (status, _) = post(JsonConfig::default(), req()).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let config = JsonConfig::default().content_type_required(false);
+        let (status, _) = post(config, req()).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn custom_error_handler_is_used() {
+        let config = JsonConfig::default().limit(8).error_handler(|error, req| {
+            RequestError::builder()
+                .code(StatusCode::UNPROCESSABLE_ENTITY)
+                .message(format!("{} rejected", req.path()))
+                .detail(Some(error.status_code().as_u16().into()))
+                .build()
+        });
+
+        let (status, body) = post(config, json_body(r#"{"name":"alice"}"#)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["message"], "/ rejected");
+        assert_eq!(body["detail"], 413);
+    }
+}
@@ -1,4 +1,14 @@
+mod de;
+mod errors;
 mod json;
+mod path;
 mod query;
+mod request_id;
+mod request_span;
 
-pub use query::Query;
+pub use errors::{ErrorResponses, ErrorResponsesMiddleware};
+pub use json::{Json, JsonConfig, JsonExtractFut};
+pub use path::{validate_segments, FromPathSegments, ValidPath};
+pub use query::{from_str, Query, QueryValues};
+pub use request_id::{AssignRequestId, AssignRequestIdMiddleware, RequestId, X_REQUEST_ID};
+pub use request_span::{RequestSpan, RequestSpanMiddleware};
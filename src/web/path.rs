@@ -0,0 +1,215 @@
+use std::{
+    cell::Cell,
+    ops::{Deref, DerefMut},
+};
+
+use actix_web::{dev::Payload, http::StatusCode, FromRequest, HttpRequest};
+use futures::future::{ready, Ready};
+use serde::de::{value::MapDeserializer, DeserializeOwned};
+use serde_json::json;
+use validator::{Validate, ValidationErrors};
+
+use super::de::TextDeserializer;
+use crate::{error::ErrorCode, fields::FromPath, RequestError};
+
+///
+/// ValidPath
+///
+/// Extracts the matched path segments of the request into `T`, failing
+/// with `INVALID_PATH_PART` naming the offending segment.
+///
+/// Types implementing `FromPath` are parsed from the route's single
+/// segment, e.g. `ValidPath<EmailOrObjectId>` for `/users/{id}`. Structs
+/// deriving `Deserialize + Validate` are filled from every named segment
+/// once declared with `path_segments!`.
+///
+pub struct ValidPath<T>(pub T);
+
+impl<T> ValidPath<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidPath<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for ValidPath<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for ValidPath<T>
+where
+    T: FromPathSegments,
+{
+    type Error = RequestError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let segments: Vec<(&str, &str)> = req.match_info().iter().collect();
+        ready(T::from_segments(&segments).map(ValidPath))
+    }
+}
+
+///
+/// Types built from the named segments of the matched route
+///
+pub trait FromPathSegments: Sized {
+    fn from_segments(segments: &[(&str, &str)]) -> Result<Self, RequestError>;
+}
+
+impl<T> FromPathSegments for T
+where
+    T: for<'a> FromPath<&'a str>,
+{
+    fn from_segments(segments: &[(&str, &str)]) -> Result<Self, RequestError> {
+        match segments {
+            [(name, value)] => T::from_path(name, value).map_err(|mut e| {
+                if e.detail.is_none() {
+                    e.detail = Some(json!({ "segment": name }));
+                }
+                e
+            }),
+            _ => Err(errs::segment_count(segments.len())),
+        }
+    }
+}
+
+///
+/// Deserializes every named segment into the fields of `T` and validates it
+///
+pub fn validate_segments<T>(segments: &[(&str, &str)]) -> Result<T, RequestError>
+where
+    T: DeserializeOwned + Validate,
+{
+    //== remember the segment being deserialized when an error is raised
+    let current: Cell<Option<&str>> = Cell::new(None);
+    let fields = segments.iter().map(|(name, value)| {
+        current.set(Some(name));
+        (*name, TextDeserializer::new(value))
+    });
+    let value = T::deserialize(MapDeserializer::new(fields))
+        .map_err(|e| errs::invalid_segment(current.get(), e.to_string()))?;
+
+    value.validate().map_err(errs::from_validation_errors)?;
+    Ok(value)
+}
+
+///
+/// Implements `FromPathSegments` for `Deserialize + Validate` structs,
+/// e.g. `path_segments!(KeyPath)` for `/users/{user_id}/keys/{key_id}`
+///
+#[macro_export]
+macro_rules! path_segments {
+    ($($name:ty),+ $(,)?) => {
+        $(
+            impl $crate::web::FromPathSegments for $name {
+                fn from_segments(
+                    segments: &[(&str, &str)],
+                ) -> Result<Self, $crate::RequestError> {
+                    $crate::web::validate_segments(segments)
+                }
+            }
+        )+
+    };
+}
+
+mod errs {
+    use super::*;
+
+    pub fn segment_count(count: usize) -> RequestError {
+        RequestError::builder()
+            .code(StatusCode::INTERNAL_SERVER_ERROR)
+            .error(ErrorCode::InternalServerError)
+            .message("Path extractor expects exactly one path segment")
+            .source(Some(json!({ "segments": count })))
+            .build()
+    }
+
+    pub fn invalid_segment(segment: Option<&str>, message: String) -> RequestError {
+        RequestError::builder()
+            .error(ErrorCode::InvalidPathPart)
+            .message(message)
+            .detail(Some(json!({ "segment": segment })))
+            .build()
+    }
+
+    pub fn from_validation_errors(errors: ValidationErrors) -> RequestError {
+        let mut segments: Vec<&str> = errors.errors().keys().copied().collect();
+        segments.sort_unstable();
+
+        RequestError::builder()
+            .error(ErrorCode::InvalidPathPart)
+            .message("Invalid path segment")
+            .detail(Some(json!({
+                "segment": segments.first(),
+                "errors": RequestError::from(errors).detail,
+            })))
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::fields::EmailOrObjectId;
+
+    #[derive(Deserialize, Validate)]
+    struct KeyPath {
+        #[validate(length(min = 3))]
+        user: String,
+        key: u32,
+    }
+
+    crate::path_segments!(KeyPath);
+
+    #[test]
+    fn from_path_uses_single_segment() {
+        let id = EmailOrObjectId::from_segments(&[("id", "62f1b2c3d4e5f6a7b8c9d0e1")]);
+        assert!(matches!(id, Ok(EmailOrObjectId::ObjectId(_))));
+    }
+
+    #[test]
+    fn from_path_error_names_segment() {
+        let error = EmailOrObjectId::from_segments(&[("id", "nope")])
+            .err()
+            .unwrap();
+        assert_eq!(error.code, StatusCode::BAD_REQUEST);
+        assert_eq!(error.error, "INVALID_PATH_PART");
+        assert_eq!(error.detail, Some(json!({ "segment": "id" })));
+    }
+
+    #[test]
+    fn struct_segments_are_parsed() {
+        let path = KeyPath::from_segments(&[("user", "alice"), ("key", "42")]).unwrap();
+        assert_eq!(path.user, "alice");
+        assert_eq!(path.key, 42);
+    }
+
+    #[test]
+    fn struct_parse_error_names_segment() {
+        let error = KeyPath::from_segments(&[("user", "alice"), ("key", "x")])
+            .err()
+            .unwrap();
+        assert_eq!(error.error, "INVALID_PATH_PART");
+        assert_eq!(error.detail, Some(json!({ "segment": "key" })));
+    }
+
+    #[test]
+    fn struct_validation_error_names_segment() {
+        let error = KeyPath::from_segments(&[("user", "al"), ("key", "1")])
+            .err()
+            .unwrap();
+        assert_eq!(error.error, "INVALID_PATH_PART");
+        assert_eq!(error.detail.unwrap()["segment"], "user");
+    }
+}
@@ -1,14 +1,24 @@
-use std::{error::Error, ops::Deref};
+use std::{error::Error as _, fmt, marker::PhantomData, ops::Deref, str::FromStr};
 
-use actix_web::http::StatusCode;
 use actix_web::FromRequest;
-use futures::future::{err, ok, Ready};
-use serde::de;
-use serde_json;
+use futures::future::{ready, Ready};
+use serde::{
+    de::{self, value::BorrowedStrDeserializer, DeserializeSeed, MapAccess, Visitor},
+    Deserialize, Serialize,
+};
+use serde_json::{self, json};
 use validator::Validate;
 
+use super::de::ValuesDeserializer;
 use crate::{error::RequestError, ErrorCode};
 
+///
+/// Query
+///
+/// Extracts and validates the query string into `T`. Repeated keys
+/// (`ids=1&ids=2`) and bracketed keys (`tags[]=x`) decode into sequences,
+/// decode errors are `INVALID_QUERY_PARAM` naming the offending param.
+///
 pub struct Query<T: Validate>(pub T);
 
 impl<T> Deref for Query<T>
@@ -29,30 +39,424 @@ where
     type Error = RequestError;
     type Future = Ready<Result<Self, Self::Error>>;
 
-    fn from_request(
-        req: &actix_web::web::HttpRequest,
-        _: &mut actix_web::dev::Payload,
-    ) -> Self::Future {
-        serde_urlencoded::from_str::<T>(req.query_string())
-            .map_err(RequestError::from)
-            .and_then(|q| q.validate().map(move |_| q).map_err(RequestError::from))
-            .map_err(RequestError::from)
-            .map(|value| ok(Query(value)))
-            .unwrap_or_else(|e| err(e))
+    fn from_request(req: &actix_web::HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
+        ready(
+            QueryParams::parse(req.query_string())
+                .and_then(|params| params.deserialize::<T>())
+                .and_then(|q| {
+                    q.validate()
+                        .map(move |_| Query(q))
+                        .map_err(RequestError::from)
+                }),
+        )
     }
 }
 
+///
+/// Decoded query params grouped by key, in order of first appearance
+///
+struct QueryParams {
+    params: Vec<(String, Vec<String>)>,
+}
+
+impl QueryParams {
+    fn parse(query: &str) -> Result<Self, RequestError> {
+        let pairs: Vec<(String, String)> = serde_urlencoded::from_str(query)?;
+        let mut params: Vec<(String, Vec<String>)> = vec![];
+
+        for (key, value) in pairs {
+            //== `key[]` is the same param as `key`
+            let key = match key.strip_suffix("[]") {
+                Some(key) => key.to_string(),
+                None => key,
+            };
+
+            match params.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, values)) => values.push(value),
+                None => params.push((key, vec![value])),
+            }
+        }
+
+        Ok(Self { params })
+    }
+
+    fn deserialize<T: de::DeserializeOwned>(&self) -> Result<T, RequestError> {
+        Self::deserialize_from::<T>(&self.params).map_err(|(error, failed)| {
+            let param = failed.or_else(|| self.replay::<T>(&error));
+            errs::invalid_query_param(param, error.to_string())
+        })
+    }
+
+    fn deserialize_from<T: de::DeserializeOwned>(
+        params: &[(String, Vec<String>)],
+    ) -> Result<T, (ParamsError, Option<&str>)> {
+        let mut deserializer = ParamsDeserializer {
+            params,
+            index: 0,
+            failed: None,
+        };
+
+        T::deserialize(&mut deserializer).map_err(|e| (e, deserializer.failed))
+    }
+
+    ///
+    /// Values of `#[serde(flatten)]`ed fields are buffered and fail after
+    /// every param was read. The param at fault is the first one whose
+    /// removal fixes or changes `error`, unless it only leaves a required
+    /// field missing
+    ///
+    fn replay<T: de::DeserializeOwned>(&self, error: &ParamsError) -> Option<&str> {
+        if error.missing_field {
+            return None;
+        }
+
+        (0..self.params.len())
+            .find(|&i| {
+                let mut params = self.params.clone();
+                params.remove(i);
+
+                match Self::deserialize_from::<T>(&params) {
+                    Ok(_) => true,
+                    Err((e, _)) => !e.missing_field && e != *error,
+                }
+            })
+            .map(|i| self.params[i].0.as_str())
+    }
+}
+
+///
+/// Error of `ParamsDeserializer`, telling missing fields apart
+///
+#[derive(Debug, PartialEq)]
+struct ParamsError {
+    message: String,
+    missing_field: bool,
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParamsError {}
+
+impl de::Error for ParamsError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self {
+            message: msg.to_string(),
+            missing_field: false,
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Self {
+            message: format!("missing field `{}`", field),
+            missing_field: true,
+        }
+    }
+}
+
+///
+/// Map of every query param, remembering the param whose value failed
+///
+struct ParamsDeserializer<'a> {
+    params: &'a [(String, Vec<String>)],
+    index: usize,
+    failed: Option<&'a str>,
+}
+
+impl<'de> de::Deserializer<'de> for &mut ParamsDeserializer<'de> {
+    type Error = ParamsError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> MapAccess<'de> for ParamsDeserializer<'de> {
+    type Error = ParamsError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.params.get(self.index) {
+            Some((key, _)) => seed
+                .deserialize(BorrowedStrDeserializer::new(key))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let (key, values) = self
+            .params
+            .get(self.index)
+            .ok_or_else(|| de::Error::custom("value requested before its key"))?;
+        self.index += 1;
+
+        seed.deserialize(ValuesDeserializer::new(values))
+            .map_err(|e| {
+                self.failed = Some(key);
+                de::Error::custom(e)
+            })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.params.len() - self.index)
+    }
+}
+
+///
+/// QueryValues
+///
+/// Every value of a repeated or bracketed query param, for the values of
+/// flattened catch-all param maps, e.g. `HashMap<String, QueryValues>`
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueryValues(Vec<String>);
+
+impl Deref for QueryValues {
+    type Target = [String];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'de> Deserialize<'de> for QueryValues {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct QueryValuesVisitor;
+
+        impl<'de> Visitor<'de> for QueryValuesVisitor {
+            type Value = QueryValues;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "one or more query values")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(QueryValues(vec![value.to_string()]))
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut values = vec![];
+                while let Some(value) = seq.next_element()? {
+                    values.push(value);
+                }
+                Ok(QueryValues(values))
+            }
+        }
+
+        deserializer.deserialize_any(QueryValuesVisitor)
+    }
+}
+
+///
+/// Deserializes a `FromStr` value from a string or a number. Typed fields
+/// of `#[serde(flatten)]`ed query structs need it, e.g.
+/// `#[serde(deserialize_with = "crate::web::from_str")]`, as serde buffers
+/// their values as strings.
+///
+pub fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: de::Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    struct FromStrVisitor<T>(PhantomData<T>);
+
+    impl<'de, T> Visitor<'de> for FromStrVisitor<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a string")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            value
+                .parse()
+                .map_err(|e| E::custom(format!("invalid value `{}`, {}", value, e)))
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+            self.visit_str(&value.to_string())
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+            self.visit_str(&value.to_string())
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+            self.visit_str(&value.to_string())
+        }
+    }
+
+    deserializer.deserialize_any(FromStrVisitor(PhantomData))
+}
+
 impl From<serde_urlencoded::de::Error> for RequestError {
     fn from(error: serde_urlencoded::de::Error) -> Self {
         RequestError::builder()
-            .code(StatusCode::INTERNAL_SERVER_ERROR)
-            .error(ErrorCode::InternalServerError)
+            .error(ErrorCode::InvalidQueryParam)
             .message("URL failed to decode")
-            .detail(Some(error.to_string().into()))
+            .detail(Some(json!({ "param": null })))
             .source(match error.source() {
                 Some(source) => Some(serde_json::Value::String(source.to_string())),
-                None => None,
+                None => Some(serde_json::Value::String(error.to_string())),
             })
             .build()
     }
 }
+
+mod errs {
+    use super::*;
+
+    pub fn invalid_query_param(param: Option<&str>, message: String) -> RequestError {
+        RequestError::builder()
+            .error(ErrorCode::InvalidQueryParam)
+            .message(message)
+            .detail(Some(json!({ "param": param })))
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::schemas::PageParams;
+
+    #[derive(Deserialize)]
+    struct ListParams {
+        ids: Vec<u32>,
+        tags: Option<Vec<String>>,
+        #[serde(flatten)]
+        page: PageParams,
+        #[serde(flatten)]
+        filters: HashMap<String, QueryValues>,
+    }
+
+    fn parse<T: de::DeserializeOwned>(query: &str) -> Result<T, RequestError> {
+        QueryParams::parse(query)?.deserialize()
+    }
+
+    #[test]
+    fn repeated_and_bracketed_keys_are_sequences() {
+        let params: ListParams = parse("ids=1&ids=2&tags[]=x&tags[]=y").unwrap();
+        assert_eq!(params.ids, vec![1, 2]);
+        assert_eq!(params.tags, Some(vec!["x".to_string(), "y".to_string()]));
+    }
+
+    #[test]
+    fn single_value_is_a_sequence_of_one() {
+        let params: ListParams = parse("ids=7").unwrap();
+        assert_eq!(params.ids, vec![7]);
+    }
+
+    #[test]
+    fn flattened_numbers_are_parsed() {
+        let params: ListParams = parse("ids=1&limit=5&offset=10").unwrap();
+        assert_eq!(params.page.limit, 5);
+        assert_eq!(params.page.offset, 10);
+    }
+
+    #[test]
+    fn flattened_catch_all_collects_values() {
+        let params: ListParams = parse("ids=1&email=a&email=b&name=c").unwrap();
+        assert_eq!(&*params.filters["email"], ["a", "b"]);
+        assert_eq!(&*params.filters["name"], ["c"]);
+    }
+
+    #[test]
+    fn flattened_catch_all_keeps_query_order() {
+        #[derive(Deserialize)]
+        struct Filters {
+            #[serde(flatten)]
+            filters: indexmap::IndexMap<String, QueryValues>,
+        }
+
+        let params: Filters = parse("email__in=b,c&name=x&email=a").unwrap();
+        let keys: Vec<&str> = params.filters.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["email__in", "name", "email"]);
+    }
+
+    #[test]
+    fn invalid_value_names_param() {
+        let error = parse::<ListParams>("ids=1&ids=x").err().unwrap();
+        assert_eq!(error.code.as_u16(), 400);
+        assert_eq!(error.error, "INVALID_QUERY_PARAM");
+        assert_eq!(error.detail, Some(json!({ "param": "ids" })));
+    }
+
+    #[test]
+    fn invalid_flattened_value_names_param() {
+        let error = parse::<ListParams>("ids=1&limit=abc").err().unwrap();
+        assert_eq!(error.error, "INVALID_QUERY_PARAM");
+        assert_eq!(error.detail, Some(json!({ "param": "limit" })));
+    }
+
+    #[test]
+    fn invalid_flattened_value_names_param_not_value() {
+        //== `name` holds the same value but is a valid filter
+        let error = parse::<ListParams>("name=abc&ids=1&limit=abc")
+            .err()
+            .unwrap();
+        assert_eq!(error.detail, Some(json!({ "param": "limit" })));
+    }
+
+    #[test]
+    fn first_invalid_flattened_value_names_param() {
+        let error = parse::<ListParams>("offset=x&ids=1&limit=abc")
+            .err()
+            .unwrap();
+        assert!(error.message.contains("`x`"));
+        assert_eq!(error.detail, Some(json!({ "param": "offset" })));
+
+        let error = parse::<ListParams>("limit=abc&ids=1&offset=x")
+            .err()
+            .unwrap();
+        assert!(error.message.contains("`abc`"));
+        assert_eq!(error.detail, Some(json!({ "param": "limit" })));
+    }
+
+    #[test]
+    fn missing_field_names_no_param() {
+        let error = parse::<ListParams>("limit=5").err().unwrap();
+        assert_eq!(error.detail, Some(json!({ "param": null })));
+    }
+
+    #[test]
+    fn invalid_page_params_name_param() {
+        let error = parse::<PageParams>("limit=abc").err().unwrap();
+        assert_eq!(error.error, "INVALID_QUERY_PARAM");
+        assert_eq!(error.detail, Some(json!({ "param": "limit" })));
+    }
+
+    #[test]
+    fn repeated_single_value_is_rejected() {
+        let error = parse::<PageParams>("cursor=a&cursor=b").err().unwrap();
+        assert_eq!(error.detail, Some(json!({ "param": "cursor" })));
+    }
+}
@@ -0,0 +1,235 @@
+use std::{fmt, future::Future, ops::Deref, rc::Rc};
+
+use actix_web::{
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue},
+    Error, FromRequest, HttpMessage, HttpRequest,
+};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use rand::Rng;
+
+pub const X_REQUEST_ID: &str = "x-request-id";
+
+/// Longest client supplied id that is accepted
+const MAX_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+///
+/// RequestId
+///
+/// Correlation id of the current request, taken from a well formed
+/// `X-Request-Id` header or generated. Extractable in handlers and
+/// available to code running in the request via `RequestId::current()`.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn generate() -> Self {
+        Self(format!("{:032x}", rand::thread_rng().gen::<u128>()))
+    }
+
+    ///
+    /// Uses a client supplied id if it is short and made of safe characters
+    ///
+    pub fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?;
+
+        let valid = !value.is_empty()
+            && value.len() <= MAX_LEN
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+
+        if valid {
+            Some(Self(value.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// Id of the request being handled by the current task, if any
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(|id| id.clone()).ok()
+    }
+
+    /// Runs `f` with `self` as the current request id
+    pub fn scope<F: Future>(self, f: F) -> impl Future<Output = F::Output> {
+        CURRENT.scope(self, f)
+    }
+
+    pub fn header_name() -> HeaderName {
+        HeaderName::from_static(X_REQUEST_ID)
+    }
+
+    pub fn header_value(&self) -> HeaderValue {
+        //== ids are validated or generated, so always a valid header value
+        HeaderValue::from_str(&self.0).unwrap()
+    }
+}
+
+impl Deref for RequestId {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ok(req
+            .extensions()
+            .get::<RequestId>()
+            .cloned()
+            .unwrap_or_else(RequestId::generate))
+    }
+}
+
+///
+/// AssignRequestId Middleware
+///
+/// Accepts or generates an `X-Request-Id`, stores it in request extensions
+/// and headers, runs the request with it as `RequestId::current()` and
+/// echoes it in the response. Register it as the outermost middleware.
+///
+pub struct AssignRequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for AssignRequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AssignRequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AssignRequestIdMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+pub struct AssignRequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AssignRequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        let id = req
+            .headers()
+            .get(X_REQUEST_ID)
+            .and_then(RequestId::from_header)
+            .unwrap_or_else(RequestId::generate);
+
+        //== replace any malformed client id so loggers see the one in use
+        req.headers_mut()
+            .insert(RequestId::header_name(), id.header_value());
+        req.extensions_mut().insert(id.clone());
+
+        Box::pin(id.clone().scope(async move {
+            let mut res = service.call(req).await?;
+
+            res.headers_mut()
+                .insert(RequestId::header_name(), id.header_value());
+
+            Ok(res)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        test::{call_service, init_service, TestRequest},
+        web, App, HttpResponse,
+    };
+
+    use super::*;
+
+    fn from_header(value: &str) -> Option<RequestId> {
+        RequestId::from_header(&HeaderValue::from_str(value).unwrap())
+    }
+
+    async fn echoed(value: &str) -> String {
+        let app = init_service(
+            App::new()
+                .wrap(AssignRequestId)
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/")
+            .insert_header((X_REQUEST_ID, value))
+            .to_request();
+        let resp = call_service(&app, req).await;
+
+        resp.headers()
+            .get(X_REQUEST_ID)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn well_formed_id_is_accepted() {
+        let id = "req-42_a.b:c";
+        assert_eq!(from_header(id), Some(RequestId(id.into())));
+        assert!(from_header(&"a".repeat(MAX_LEN)).is_some());
+    }
+
+    #[test]
+    fn too_long_id_is_rejected() {
+        assert_eq!(from_header(&"a".repeat(MAX_LEN + 1)), None);
+    }
+
+    #[test]
+    fn id_with_bad_characters_is_rejected() {
+        for value in ["", "a b", "a/b", "a\"b", "<script>", "a\tb"] {
+            assert_eq!(from_header(value), None, "{:?}", value);
+        }
+        let non_ascii = HeaderValue::from_bytes("idé".as_bytes()).unwrap();
+        assert_eq!(RequestId::from_header(&non_ascii), None);
+    }
+
+    #[actix_web::test]
+    async fn valid_id_is_echoed() {
+        assert_eq!(echoed("req-42").await, "req-42");
+    }
+
+    #[actix_web::test]
+    async fn invalid_id_is_replaced() {
+        let id = echoed(&"a".repeat(MAX_LEN + 1)).await;
+        assert_eq!(id.len(), 32);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+}
@@ -0,0 +1,197 @@
+use std::{rc::Rc, time::Instant};
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::StatusCode,
+    Error, HttpMessage,
+};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use tracing::{field::Empty, Instrument};
+
+use super::RequestId;
+use crate::metrics;
+
+///
+/// RequestSpan Middleware
+///
+/// Runs each request in a `request` span carrying the method, route
+/// pattern and request id, and records the status and latency in an event
+/// and in the http metrics once the response is ready. Register it inside
+/// `AssignRequestId`.
+///
+pub struct RequestSpan;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestSpan
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestSpanMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestSpanMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+pub struct RequestSpanMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestSpanMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        //== patterns keep span cardinality low, unmatched paths share one
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| "<unmatched>".to_string());
+        let request_id = req
+            .extensions()
+            .get::<RequestId>()
+            .map(|id| id.0.clone())
+            .unwrap_or_default();
+
+        let method = req.method().clone();
+        let span = tracing::info_span!(
+            "request",
+            method = %method,
+            route = %route,
+            request_id = %request_id,
+            status = Empty,
+            latency_ms = Empty,
+        );
+
+        Box::pin(
+            async move {
+                let start = Instant::now();
+                let res = service.call(req).await;
+
+                //== errors from inner middleware become responses further out
+                let status = match res {
+                    Ok(ref res) => res.status(),
+                    Err(ref e) => e.as_response_error().status_code(),
+                };
+                let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+                let labels = [
+                    metrics::method_label(method.as_str()),
+                    route.as_str(),
+                    status.as_str(),
+                ];
+                metrics::HTTP_REQUESTS.with_label_values(&labels).inc();
+                metrics::HTTP_REQUEST_DURATION
+                    .with_label_values(&labels)
+                    .observe(latency_ms / 1000.0);
+
+                let span = tracing::Span::current();
+                span.record("status", status.as_u16());
+                span.record("latency_ms", latency_ms);
+
+                if status.is_server_error() || status == StatusCode::REQUEST_TIMEOUT {
+                    tracing::error!(status = status.as_u16(), latency_ms, "request failed");
+                } else {
+                    tracing::info!(status = status.as_u16(), latency_ms, "request completed");
+                }
+
+                res
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fmt,
+        sync::{Arc, Mutex},
+    };
+
+    use actix_web::{
+        test::{call_service, init_service, TestRequest},
+        web, App, HttpResponse,
+    };
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Subscriber,
+    };
+    use tracing_subscriber::{layer::Context, prelude::*, Layer};
+
+    use super::*;
+    use crate::web::{AssignRequestId, X_REQUEST_ID};
+
+    type Fields = Arc<Mutex<HashMap<String, String>>>;
+
+    ///
+    /// Captures the fields of `request` spans
+    ///
+    #[derive(Clone, Default)]
+    struct Capture(Fields);
+
+    impl Visit for Capture {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for Capture {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+            if attrs.metadata().name() == "request" {
+                attrs.record(&mut self.clone());
+            }
+        }
+
+        fn on_record(&self, _: &Id, values: &Record<'_>, _: Context<'_, S>) {
+            values.record(&mut self.clone());
+        }
+    }
+
+    #[actix_web::test]
+    async fn span_carries_request_fields() {
+        let capture = Capture::default();
+        let fields = capture.0.clone();
+        let _default =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(capture));
+
+        let app = init_service(App::new().wrap(RequestSpan).wrap(AssignRequestId).route(
+            "/users/{id}",
+            web::get().to(|| async { HttpResponse::Created().finish() }),
+        ))
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/users/42")
+            .insert_header((X_REQUEST_ID, "span-test-id"))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let fields = fields.lock().unwrap();
+        assert_eq!(fields["method"], "GET");
+        assert_eq!(fields["route"], "/users/{id}");
+        assert_eq!(fields["request_id"], "span-test-id");
+        assert_eq!(fields["status"], "201");
+        assert!(fields.contains_key("latency_ms"));
+    }
+}